@@ -0,0 +1,177 @@
+// Animations go to the device as GIFs of panel sized frames sharing one global palette of at most 256 colours,
+// each shown for a whole number of hundredths of a second and looping forever.
+use crate::config::Panel;
+use color_quant::NeuQuant;
+use image::{
+    AnimationDecoder, Delay, Frame, ImageError, ImageFormat, RgbImage,
+    codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+    error::{EncodingError, ImageFormatHint, ParameterError, ParameterErrorKind},
+};
+use std::{borrow::Cow, collections::HashMap, io::Cursor};
+
+// Players show shorter delays as the default, so these are the shortest that play as written.
+const MIN_DELAY_CS: u16 = 2;
+// What players use for frames without a delay.
+const DEFAULT_DELAY_CS: u16 = 10;
+const PALETTE_SIZE: usize = 256;
+// NeuQuant samples every nth pixel, 1 is the slowest and best, 10 a good trade-off.
+const QUANTISE_SAMPLING: i32 = 10;
+
+// A frame's delay in the GIF unit of hundredths of a second.
+pub fn delay_cs(delay: Delay) -> u16 {
+    let (numer, denom) = delay.numer_denom_ms();
+    if numer == 0 {
+        return DEFAULT_DELAY_CS;
+    }
+    let cs = (numer as f64 / denom as f64 / 10.0).round().min(u16::MAX as f64) as u16;
+    cs.max(MIN_DELAY_CS)
+}
+
+// Every frame of a GIF, animated PNG or animated WebP file, None for still images.
+pub fn frames(data: &[u8], format: ImageFormat) -> Result<Option<Vec<Frame>>, ImageError> {
+    let frames = match format {
+        ImageFormat::Gif => GifDecoder::new(Cursor::new(data))?.into_frames(),
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(Cursor::new(data))?;
+            if !decoder.is_apng()? {
+                return Ok(None);
+            }
+            decoder.apng()?.into_frames()
+        }
+        ImageFormat::WebP => {
+            let decoder = WebPDecoder::new(Cursor::new(data))?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            decoder.into_frames()
+        }
+        _ => return Ok(None),
+    };
+    frames.collect_frames().map(Some)
+}
+
+// Length of the GIF at the start of `data`, up to and including its trailer, found by walking its blocks.
+pub fn gif_len(data: &[u8]) -> Option<usize> {
+    // a colour table follows when the flags' top bit is set, 3 bytes times 2^(size + 1) colours
+    let table = |flags: u8| if flags & 0x80 != 0 { 3 << ((flags & 0x07) + 1) } else { 0 };
+    // sub-blocks each start with their length, a zero length ends them
+    let skip_sub_blocks = |mut at: usize| loop {
+        let len = *data.get(at)? as usize;
+        at += 1 + len;
+        if len == 0 {
+            return Some(at);
+        }
+    };
+    if !data.starts_with(b"GIF8") {
+        return None;
+    }
+    // signature and logical screen descriptor
+    let mut at = 13 + table(*data.get(10)?);
+    loop {
+        match *data.get(at)? {
+            0x21 => at = skip_sub_blocks(at + 2)?,
+            0x2c => at = skip_sub_blocks(at + 10 + table(*data.get(at + 9)?) + 1)?,
+            0x3b => return (at < data.len()).then_some(at + 1),
+            _ => return None,
+        }
+    }
+}
+
+// Checks an existing GIF against the rules above, so it can be sent without decoding and re-encoding it.
+pub fn conforms(data: &[u8], panel: Panel) -> bool {
+    let mut options = gif::DecodeOptions::new();
+    options.skip_frame_decoding(true);
+    let Ok(mut decoder) = options.read_info(data) else {
+        return false;
+    };
+    if (decoder.width(), decoder.height()) != (panel.width, panel.height)
+        || decoder.global_palette().is_none()
+        || decoder.repeat() != gif::Repeat::Infinite
+    {
+        return false;
+    }
+    let mut frames = 0;
+    loop {
+        match decoder.read_next_frame() {
+            Ok(Some(frame)) => {
+                let full = (frame.left, frame.top, frame.width, frame.height) == (0, 0, panel.width, panel.height);
+                if !full || frame.interlaced || frame.palette.is_some() || frame.transparent.is_some() || frame.delay < MIN_DELAY_CS {
+                    return false;
+                }
+                frames += 1;
+            }
+            Ok(None) => return frames > 0,
+            Err(_) => return false,
+        }
+    }
+}
+
+enum Palette {
+    // images with few enough colours keep them exactly
+    Exact(HashMap<[u8; 3], u8>, Vec<u8>),
+    Quantised(NeuQuant),
+}
+
+impl Palette {
+    fn new(frames: &[(RgbImage, u16)]) -> Self {
+        let pixels = || frames.iter().flat_map(|(frame, _)| frame.pixels());
+        let mut indices = HashMap::new();
+        let mut colours = Vec::new();
+        for pixel in pixels() {
+            if indices.contains_key(&pixel.0) {
+                continue;
+            }
+            if indices.len() == PALETTE_SIZE {
+                let rgba: Vec<u8> = pixels().flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255]).collect();
+                return Palette::Quantised(NeuQuant::new(QUANTISE_SAMPLING, PALETTE_SIZE, &rgba));
+            }
+            indices.insert(pixel.0, indices.len() as u8);
+            colours.extend(pixel.0);
+        }
+        Palette::Exact(indices, colours)
+    }
+
+    fn colours(&self) -> Vec<u8> {
+        match self {
+            Palette::Exact(_, colours) => colours.clone(),
+            Palette::Quantised(quantiser) => quantiser.color_map_rgb(),
+        }
+    }
+
+    fn index(&self, [r, g, b]: [u8; 3]) -> u8 {
+        match self {
+            Palette::Exact(indices, _) => indices[&[r, g, b]],
+            Palette::Quantised(quantiser) => quantiser.index_of(&[r, g, b, 255]) as u8,
+        }
+    }
+}
+
+fn encoding_error(e: gif::EncodingError) -> ImageError {
+    ImageError::Encoding(EncodingError::new(ImageFormatHint::Exact(ImageFormat::Gif), e))
+}
+
+// Encodes panel sized frames with their delays in hundredths of a second.
+pub fn encode(frames: &[(RgbImage, u16)], panel: Panel) -> Result<Vec<u8>, ImageError> {
+    if frames.is_empty() {
+        return Err(ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::NoMoreData)));
+    }
+    let palette = Palette::new(frames);
+    let mut data = Vec::new();
+    {
+        let mut encoder =
+            gif::Encoder::new(&mut data, panel.width, panel.height, &palette.colours()).map_err(encoding_error)?;
+        encoder.set_repeat(gif::Repeat::Infinite).map_err(encoding_error)?;
+        for (image, delay) in frames {
+            let indices: Vec<u8> = image.pixels().map(|pixel| palette.index(pixel.0)).collect();
+            let frame = gif::Frame {
+                width: panel.width,
+                height: panel.height,
+                delay: (*delay).max(MIN_DELAY_CS),
+                buffer: Cow::Owned(indices),
+                ..Default::default()
+            };
+            encoder.write_frame(&frame).map_err(encoding_error)?;
+        }
+    }
+    Ok(data)
+}
@@ -0,0 +1,132 @@
+#![cfg_attr(not(unix), allow(unused))]
+use clap::Parser;
+use iledcolor_rs::{
+    ble::{self, AdapterSelector, ConnectOptions, Connector},
+    config::Inventory,
+    sim::Simulator,
+};
+use std::{error::Error, path::PathBuf, sync::Arc, time::Duration};
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Keeps collars connected and takes JSON commands over a unix socket")]
+pub struct Cli {
+    /// TOML or YAML (.yaml, .yml) inventory of the devices to keep connected
+    #[arg(long)]
+    config: PathBuf,
+    /// Socket to listen on, defaults to $XDG_RUNTIME_DIR/iledd.sock
+    #[arg(short, long)]
+    socket: Option<PathBuf>,
+    /// Bluetooth adapter to use, by index or name/address
+    #[arg(short, long)]
+    adapter: Option<AdapterSelector>,
+    /// Seconds to scan for a device before retrying later, 0 scans forever
+    #[arg(long, default_value_t = 30)]
+    scan_timeout: u64,
+    /// Connection attempts retried before giving up
+    #[arg(long, default_value_t = 3)]
+    retries: u32,
+    /// Serve simulated devices instead of bluetooth ones
+    #[arg(long)]
+    simulate: bool,
+    /// Also serve the HTTP API on this address, e.g. 127.0.0.1:8080
+    #[cfg(feature = "http")]
+    #[arg(long)]
+    http: Option<std::net::SocketAddr>,
+    /// Bridge the devices to the MQTT broker at this host[:port], e.g. localhost for a local broker
+    #[cfg(feature = "mqtt")]
+    #[arg(long)]
+    mqtt: Option<String>,
+    /// First level of the MQTT topics
+    #[cfg(feature = "mqtt")]
+    #[arg(long, default_value = "iledcolor", requires = "mqtt")]
+    mqtt_prefix: String,
+    /// Prefix Home Assistant reads MQTT discovery configs from
+    #[cfg(feature = "mqtt")]
+    #[arg(long, default_value = "homeassistant", requires = "mqtt")]
+    discovery_prefix: String,
+    /// MQTT credentials as user:password
+    #[cfg(feature = "mqtt")]
+    #[arg(long, requires = "mqtt")]
+    mqtt_credentials: Option<String>,
+    /// Directory MQTT image commands may read files from, local files are refused without it
+    #[cfg(feature = "mqtt")]
+    #[arg(long, requires = "mqtt")]
+    mqtt_image_dir: Option<PathBuf>,
+    /// Let MQTT image commands fetch http(s) URLs
+    #[cfg(feature = "mqtt")]
+    #[arg(long, requires = "mqtt")]
+    mqtt_fetch_urls: bool,
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("iledd listens on a unix socket and is only available on unix systems");
+}
+
+#[cfg(unix)]
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    use iledcolor_rs::daemon::{self, Daemon};
+
+    env_logger::init();
+    let cli = Cli::parse();
+    let inventory = Inventory::load(&cli.config)?;
+
+    let connector = if cli.simulate {
+        let sim = Simulator::new();
+        for entry in &inventory.devices {
+            sim.add(entry.target(), entry.password());
+        }
+        Connector::Sim(sim)
+    } else {
+        let options = ConnectOptions {
+            scan_timeout: (cli.scan_timeout > 0).then(|| Duration::from_secs(cli.scan_timeout)),
+            connect_retries: cli.retries,
+            ..Default::default()
+        };
+        Connector::Ble {
+            adapter: ble::adapter(cli.adapter.as_ref()).await?,
+            options,
+        }
+    };
+
+    let socket = cli.socket.unwrap_or_else(|| {
+        std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir)
+            .join("iledd.sock")
+    });
+    let listener = daemon::bind(&socket)?;
+    println!("Listening on {}", socket.display());
+    let daemon = Arc::new(Daemon::new(&inventory, connector));
+    #[cfg(feature = "http")]
+    if let Some(addr) = cli.http {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        println!("Serving HTTP on {}", addr);
+        tokio::spawn(iledcolor_rs::http::serve(daemon.clone(), listener));
+    }
+    #[cfg(feature = "mqtt")]
+    if let Some(broker) = cli.mqtt {
+        let (host, port) = match broker.rsplit_once(':') {
+            Some((host, port)) => (host.to_string(), port.parse().map_err(|_| format!("invalid MQTT port {}", port))?),
+            None => (broker, 1883),
+        };
+        let credentials = cli
+            .mqtt_credentials
+            .map(|c| c.split_once(':').map(|(u, p)| (u.to_string(), p.to_string())).ok_or("MQTT credentials must be user:password"))
+            .transpose()?;
+        let options = iledcolor_rs::mqtt::BridgeOptions {
+            host,
+            port,
+            prefix: cli.mqtt_prefix,
+            discovery_prefix: cli.discovery_prefix,
+            credentials,
+            image_dir: cli.mqtt_image_dir,
+            fetch_urls: cli.mqtt_fetch_urls,
+        };
+        println!("Bridging to MQTT broker {}:{}", options.host, options.port);
+        tokio::spawn(iledcolor_rs::mqtt::bridge(daemon.clone(), options));
+    }
+    daemon.serve(listener).await?;
+    Ok(())
+}
@@ -1,5 +1,9 @@
-use bluest::{Adapter, BluetoothUuidExt, Device, DeviceId, Uuid, btuuid,Characteristic};
-use log::{debug, error, info};
+use crate::error::Error;
+use bluest::{Characteristic, ConnectionEvent, Uuid, error::ErrorKind};
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use std::{fmt, str::FromStr, time::Duration};
+use tokio::{sync::{mpsc, oneshot, watch}, time::{sleep, timeout}};
 use tokio_stream::StreamExt;
 
 // pub const _BLUETOOTH_MAC: [u8; 6] = [0x9E, 0x19, 0x3D, 0x7C, 0x21, 0xBE];        leftovers..
@@ -26,95 +30,637 @@ pub struct ILEDDev {
 }
 
 impl ILEDDev {
-    pub async fn new(device: Device) ->  Self {
-        let services = device
-            .services()
-            .await
-            .expect("Service discovery failed");
+    pub async fn new(device: &bluest::Device) -> Result<Self, bluest::Error> {
+        let services = device.services().await?;
         let write_service = services
             .iter()
             .find(|s|s.uuid() == WRITE_SERVICE_UUID)
-            .expect("No matching service found");
-        let chars = write_service
-            .characteristics()
-            .await
-            .expect("Characteristic discovery failed");
+            .ok_or(ErrorKind::NotFound)?;
+        let chars = write_service.characteristics().await?;
         let gen_service = services
             .iter()
             .find(|s|s.uuid() == _GENERIC_SERVICE_UUID)
-            .expect("Generic service not found");
-        let gen_chars = gen_service
-            .characteristics()
-            .await
-            .expect("generic characteristics not found");
-        ILEDDev {
-            cmd_char: chars
+            .ok_or(ErrorKind::NotFound)?;
+        let gen_chars = gen_service.characteristics().await?;
+        let find_char = |chars: &[Characteristic], uuid: Uuid| {
+            chars
                 .iter()
-                .find(|c| c.uuid() == CMD_CHARIC_UUID)
-                .expect("Characteristic 0 not found").clone(),
-            write_char: chars
-                .iter()
-                .find(|c| c.uuid() == WRITE_CHARIC_UUID)
-                .expect("Characteristic 1 not found").clone(),
-            notify_char: chars
+                .find(|c| c.uuid() == uuid)
+                .cloned()
+                .ok_or(ErrorKind::NotFound)
+        };
+        Ok(ILEDDev {
+            cmd_char: find_char(&chars, CMD_CHARIC_UUID)?,
+            write_char: find_char(&chars, WRITE_CHARIC_UUID)?,
+            notify_char: find_char(&chars, NOTIFY_CHARIC_UUID)?,
+            name_char: find_char(&gen_chars, _DEVICE_NAME_UUID)?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConnectOptions {
+    pub scan_timeout: Option<Duration>, // None scans until the device shows up
+    pub connect_retries: u32,
+    pub backoff: Duration,              // doubled after every failed attempt
+    pub response_timeout: Duration,     // how long a command waits for its reply before giving up on it
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        ConnectOptions {
+            scan_timeout: Some(Duration::from_secs(30)),
+            connect_retries: 3,
+            backoff: Duration::from_millis(500),
+            response_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+// Runs connect attempts with exponential backoff, shared by the bluest and bluez backends.
+pub(crate) async fn retry<T, E, F, Fut>(what: &str, options: &ConnectOptions, mut attempt_fn: F) -> Result<T, E>
+where
+    E: fmt::Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut backoff = options.backoff;
+    let mut attempt = 0;
+    loop {
+        match attempt_fn().await {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < options.connect_retries => {
+                attempt += 1;
+                warn!(
+                    "{} failed ({}), retry {}/{} in {:?}",
+                    what,
+                    e,
+                    attempt,
+                    options.connect_retries,
+                    backoff
+                );
+                sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// Adapters are picked by their position in `adapters()` or by name/address.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdapterSelector {
+    Index(usize),
+    Id(String),
+}
+
+impl FromStr for AdapterSelector {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.parse() {
+            Ok(index) => AdapterSelector::Index(index),
+            Err(_) => AdapterSelector::Id(s.to_string()),
+        })
+    }
+}
+
+impl AdapterSelector {
+    pub fn matches(&self, info: &AdapterInfo) -> bool {
+        match self {
+            AdapterSelector::Index(index) => info.index == *index,
+            AdapterSelector::Id(id) => info.id == *id || info.address.eq_ignore_ascii_case(id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AdapterInfo {
+    pub index: usize,
+    pub id: String,
+    pub address: String,
+    pub available: bool,
+}
+
+impl fmt::Display for AdapterInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} {} ({})",
+            self.index,
+            self.id,
+            self.address,
+            if self.available { "available" } else { "unavailable" }
+        )
+    }
+}
+
+// Devices are matched by advertised name or id, a prefix match collects everything seen until the scan deadline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceMatcher {
+    Names(Vec<String>),
+    Prefix(String),
+}
+
+impl DeviceMatcher {
+    pub fn matches(&self, name: Option<&str>, id: &str) -> bool {
+        match self {
+            DeviceMatcher::Names(names) => names
                 .iter()
-                .find(|c| c.uuid() == NOTIFY_CHARIC_UUID)
-                .expect("Notify Characteristic not found").clone(),
-            name_char: gen_chars
+                .any(|n| Some(n.as_str()) == name || n.eq_ignore_ascii_case(id)),
+            DeviceMatcher::Prefix(prefix) => name.is_some_and(|name| name.starts_with(prefix.as_str())),
+        }
+    }
+
+    // Names that none of the found devices answer to, always empty for a prefix.
+    pub fn missing(&self, found: &[(Option<String>, String)]) -> Vec<String> {
+        match self {
+            DeviceMatcher::Names(names) => names
                 .iter()
-                .find(|c|c.uuid() == _DEVICE_NAME_UUID)
-                .expect("name characteristic not found").clone(),
+                .filter(|n| {
+                    !found
+                        .iter()
+                        .any(|(name, id)| name.as_deref() == Some(n.as_str()) || n.eq_ignore_ascii_case(id))
+                })
+                .cloned()
+                .collect(),
+            DeviceMatcher::Prefix(_) => Vec::new(),
+        }
+    }
+
+    pub fn is_complete(&self, found: &[(Option<String>, String)]) -> bool {
+        match self {
+            DeviceMatcher::Names(_) => self.missing(found).is_empty(),
+            DeviceMatcher::Prefix(_) => false,
+        }
+    }
+
+    // A prefix scan only ends at the deadline, so it falls back to the default one instead of scanning forever.
+    pub(crate) fn deadline(&self, options: &ConnectOptions) -> Option<Duration> {
+        match self {
+            DeviceMatcher::Prefix(_) => options.scan_timeout.or(ConnectOptions::default().scan_timeout),
+            DeviceMatcher::Names(_) => options.scan_timeout,
         }
-        
     }
 }
 
-pub async fn find(name: &str) -> Result<Option<Device>, bluest::Error> {
-    let adapter = Adapter::default()
+#[cfg(target_os = "linux")]
+pub async fn adapters() -> Result<Vec<AdapterInfo>, Error> {
+    crate::bluez::adapters().await
+}
+
+// bluest only exposes the system default adapter outside of linux.
+#[cfg(not(target_os = "linux"))]
+pub async fn adapters() -> Result<Vec<AdapterInfo>, Error> {
+    let available = match bluest::Adapter::default().await {
+        Some(adapter) => adapter.wait_available().await.is_ok(),
+        None => false,
+    };
+    Ok(vec![AdapterInfo {
+        index: 0,
+        id: "default".to_string(),
+        address: String::new(),
+        available,
+    }])
+}
+
+#[derive(Debug, Clone)]
+pub enum Adapter {
+    Default(bluest::Adapter),
+    #[cfg(target_os = "linux")]
+    Bluez(bluer::Adapter),
+}
+
+#[derive(Debug, Clone)]
+pub enum Device {
+    Default(bluest::Device),
+    #[cfg(target_os = "linux")]
+    Bluez(bluer::Device),
+}
+
+impl Device {
+    pub fn id(&self) -> String {
+        match self {
+            Device::Default(device) => device.id().to_string(),
+            #[cfg(target_os = "linux")]
+            Device::Bluez(device) => device.address().to_string(),
+        }
+    }
+
+    pub async fn name(&self) -> Option<String> {
+        match self {
+            Device::Default(device) => device.name_async().await.ok(),
+            #[cfg(target_os = "linux")]
+            Device::Bluez(device) => device.name().await.ok().flatten(),
+        }
+    }
+}
+
+// No selector uses the system default adapter through bluest, on linux a selected adapter goes through bluez directly.
+pub async fn adapter(selector: Option<&AdapterSelector>) -> Result<Adapter, Error> {
+    let Some(selector) = selector else {
+        return default_adapter().await;
+    };
+    let info = adapters()
+        .await?
+        .into_iter()
+        .find(|info| selector.matches(info))
+        .ok_or_else(|| Error::NoAdapter(format!("{:?}", selector)))?;
+    if !info.available {
+        return Err(Error::NoAdapter(format!("{} is not powered", info.id)));
+    }
+    #[cfg(target_os = "linux")]
+    {
+        Ok(Adapter::Bluez(crate::bluez::adapter(&info.id).await?))
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        default_adapter().await
+    }
+}
+
+async fn default_adapter() -> Result<Adapter, Error> {
+    let adapter = bluest::Adapter::default()
         .await
-        .expect("Bluetooth adapter not found");
+        .ok_or(ErrorKind::AdapterUnavailable)?;
     adapter.wait_available().await?;
+    Ok(Adapter::Default(adapter))
+}
 
-    debug!("Check for connected devices");
-    let connected_devices = adapter.connected_devices().await?;
-    for device in connected_devices {
-        if let Ok(dev_name) = device.name()
-            && dev_name == name
-        {
-            info!("Found connected BLE device: {} {}", dev_name, device.id());
-            return Ok(Some(device));
+impl Adapter {
+    pub async fn find(&self, name: &str, options: &ConnectOptions) -> Result<Option<Device>, Error> {
+        let matcher = DeviceMatcher::Names(vec![name.to_string()]);
+        Ok(self.find_all(&matcher, options).await?.into_iter().next())
+    }
+
+    pub async fn find_all(&self, matcher: &DeviceMatcher, options: &ConnectOptions) -> Result<Vec<Device>, Error> {
+        match self {
+            Adapter::Default(adapter) => Ok(find_all(adapter, matcher, options)
+                .await?
+                .into_iter()
+                .map(Device::Default)
+                .collect()),
+            #[cfg(target_os = "linux")]
+            Adapter::Bluez(adapter) => Ok(crate::bluez::find_all(adapter, matcher, options)
+                .await?
+                .into_iter()
+                .map(Device::Bluez)
+                .collect()),
         }
     }
 
-    info!("Could not find connected device, starting scan...");
+    // Connects the device if needed and resolves its characteristics.
+    pub async fn open(&self, device: Device, options: ConnectOptions) -> Result<Link, Error> {
+        match (self, device) {
+            (Adapter::Default(adapter), Device::Default(device)) => {
+                if !device.is_connected().await {
+                    connect(adapter, &device, &options).await?;
+                }
+                Ok(Link::Default(BleLink::open(adapter.clone(), device, options).await?))
+            }
+            #[cfg(target_os = "linux")]
+            (Adapter::Bluez(_), Device::Bluez(device)) => {
+                if !device.is_connected().await? {
+                    crate::bluez::connect(&device, &options).await?;
+                }
+                Ok(Link::Bluez(crate::bluez::BluezLink::open(device, options).await?))
+            }
+            #[cfg(target_os = "linux")]
+            _ => Err(ErrorKind::InvalidParameter.into()),
+        }
+    }
+}
+
+// What a device search has found so far, shared by the bluest and bluez backends.
+pub(crate) struct Search<'a, D> {
+    matcher: &'a DeviceMatcher,
+    found: Vec<D>,
+    seen: Vec<(Option<String>, String)>,
+}
+
+impl<'a, D> Search<'a, D> {
+    pub(crate) fn new(matcher: &'a DeviceMatcher) -> Self {
+        Search { matcher, found: Vec::new(), seen: Vec::new() }
+    }
+
+    pub(crate) fn has_seen(&self, id: &str) -> bool {
+        self.seen.iter().any(|(_, seen_id)| seen_id == id)
+    }
+
+    // Keeps the device if it matches and hasn't been found already, returns whether it was kept.
+    pub(crate) fn offer(&mut self, device: D, name: Option<String>, id: String) -> bool {
+        if self.has_seen(&id) {
+            return false;
+        }
+        if !self.matcher.matches(name.as_deref(), &id) {
+            debug!("[{}]", name.as_deref().unwrap_or("(unknown)"));
+            return false;
+        }
+        info!("Found {} {}", name.as_deref().unwrap_or("(unknown)"), id);
+        self.seen.push((name, id));
+        self.found.push(device);
+        true
+    }
+
+    pub(crate) fn is_complete(&self) -> bool {
+        self.matcher.is_complete(&self.seen)
+    }
+
+    pub(crate) fn into_found(self) -> Vec<D> {
+        self.found
+    }
+}
+
+// Runs a scan until it returns or the deadline passes.
+pub(crate) async fn until_deadline<E>(deadline: Option<Duration>, scan: impl Future<Output = Result<(), E>>) -> Result<(), E> {
+    match deadline {
+        Some(deadline) => timeout(deadline, scan).await.unwrap_or_else(|_| {
+            info!("Scan deadline of {:?} reached", deadline);
+            Ok(())
+        }),
+        None => scan.await,
+    }
+}
+
+pub async fn find_all(adapter: &bluest::Adapter, matcher: &DeviceMatcher, options: &ConnectOptions) -> Result<Vec<bluest::Device>, bluest::Error> {
+    let mut search = Search::new(matcher);
+    debug!("Check for connected devices");
+    for device in adapter.connected_devices().await? {
+        let id = device.id().to_string();
+        search.offer(device.clone(), device.name().ok(), id);
+    }
+    if !search.is_complete() {
+        info!("Could not find all connected devices, starting scan...");
+        until_deadline(matcher.deadline(options), scan(adapter, &mut search)).await?;
+    }
+    Ok(search.into_found())
+}
+
+async fn scan(adapter: &bluest::Adapter, search: &mut Search<'_, bluest::Device>) -> Result<(), bluest::Error> {
     let mut scan = adapter.scan(&[]).await?;
     while let Some(discovered_device) = scan.next().await {
+        let id = discovered_device.device.id().to_string();
         match discovered_device.device.name() {
-            Ok(dev_name) if dev_name == name => {
-                info!("Found {}", dev_name);
-                debug!(
-                    "Found BLE device: {} {} {:?}",
-                    dev_name,
-                    discovered_device.device.id(),
-                    discovered_device.adv_data.services
-                );
-                adapter.connect_device(&discovered_device.device).await?;
-                return Ok(Some(discovered_device.device));
-            }
-            Ok(_) => {
-                debug!(
-                    "[{}]",
-                    discovered_device
-                        .device
-                        .name()
-                        .as_deref()
-                        .unwrap_or("(unknown)"),
-                );
+            Ok(dev_name) => {
+                debug!("Advertised services of {}: {:?}", id, discovered_device.adv_data.services);
+                if search.offer(discovered_device.device, Some(dev_name), id) && search.is_complete() {
+                    return Ok(());
+                }
             }
             Err(e) => {
                 error!("Error retrieving device name: {}", e);
             }
         }
     }
-    Ok(None)
+    Ok(())
+}
+
+pub async fn connect(adapter: &bluest::Adapter, device: &bluest::Device, options: &ConnectOptions) -> Result<(), bluest::Error> {
+    let what = format!("Connecting to {}", device.id());
+    retry(&what, options, || adapter.connect_device(device)).await
+}
+
+// Spawns a watcher on the adapter's connection events, the receiver holds false once the device drops.
+pub fn watch_connection(adapter: bluest::Adapter, device: bluest::Device) -> watch::Receiver<bool> {
+    let (tx, rx) = watch::channel(true);
+    tokio::spawn(async move {
+        let mut events = match adapter.device_connection_events(&device).await {
+            Ok(events) => events,
+            Err(e) => {
+                error!("Unable to watch connection events for {}: {}", device.id(), e);
+                return;
+            }
+        };
+        while let Some(event) = events.next().await {
+            debug!("Connection event for {}: {:?}", device.id(), event);
+            if tx.send(event == ConnectionEvent::Connected).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+// Forwards notifications from a spawned task so the link doesn't borrow its own characteristic,
+// returns once the subscription is active so nothing sent afterwards can miss its response.
+async fn subscribe(notify_char: Characteristic) -> Result<mpsc::Receiver<Result<Vec<u8>, bluest::Error>>, Error> {
+    let (tx, rx) = mpsc::channel(16);
+    let (ready_tx, ready_rx) = oneshot::channel();
+    tokio::spawn(async move {
+        let mut updates = match notify_char.notify().await {
+            Ok(updates) => {
+                let _ = ready_tx.send(Ok(()));
+                updates
+            }
+            Err(e) => {
+                let _ = ready_tx.send(Err(e));
+                return;
+            }
+        };
+        while let Some(update) = updates.next().await {
+            if tx.send(update).await.is_err() {
+                break;
+            }
+        }
+    });
+    ready_rx.await.map_err(|_| Error::NoResponse)??;
+    Ok(rx)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GattCharacteristic {
+    pub uuid: Uuid,
+    pub properties: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GattService {
+    pub uuid: Uuid,
+    pub characteristics: Vec<GattCharacteristic>,
+}
+
+pub(crate) fn property_names(properties: bluest::CharacteristicProperties) -> Vec<&'static str> {
+    [
+        (properties.broadcast, "broadcast"),
+        (properties.read, "read"),
+        (properties.write_without_response, "write-without-response"),
+        (properties.write, "write"),
+        (properties.notify, "notify"),
+        (properties.indicate, "indicate"),
+        (properties.authenticated_signed_writes, "authenticated-signed-writes"),
+        (properties.extended_properties, "extended-properties"),
+    ]
+    .into_iter()
+    .filter_map(|(set, name)| set.then_some(name))
+    .collect()
+}
+
+pub struct BleLink {
+    adapter: bluest::Adapter,
+    device: bluest::Device,
+    dev: ILEDDev,
+    updates: mpsc::Receiver<Result<Vec<u8>, bluest::Error>>,
+    connected: watch::Receiver<bool>,
+    options: ConnectOptions,
+}
+
+impl BleLink {
+    pub async fn open(adapter: bluest::Adapter, device: bluest::Device, options: ConnectOptions) -> Result<Self, Error> {
+        let connected = watch_connection(adapter.clone(), device.clone());
+        let dev = ILEDDev::new(&device).await?;
+        debug!("Subscribing to notifications...");
+        let updates = subscribe(dev.notify_char.clone()).await?;
+        Ok(BleLink { adapter, device, dev, updates, connected, options })
+    }
+
+    async fn reconnect(&mut self) -> Result<(), Error> {
+        connect(&self.adapter, &self.device, &self.options).await?;
+        self.connected = watch_connection(self.adapter.clone(), self.device.clone());
+        self.dev = ILEDDev::new(&self.device).await?;
+        self.updates = subscribe(self.dev.notify_char.clone()).await?;
+        Ok(())
+    }
+
+    async fn services(&self) -> Result<Vec<GattService>, Error> {
+        let mut services = Vec::new();
+        for service in self.device.services().await? {
+            let mut characteristics = Vec::new();
+            for charic in service.characteristics().await? {
+                characteristics.push(GattCharacteristic {
+                    uuid: charic.uuid(),
+                    properties: property_names(charic.properties().await?),
+                });
+            }
+            services.push(GattService { uuid: service.uuid(), characteristics });
+        }
+        Ok(services)
+    }
+
+    // Waits for the device's notification, bailing out if the connection drops in the meantime.
+    async fn notification(&mut self) -> Result<Vec<u8>, Error> {
+        tokio::select! {
+            update = self.updates.recv() => Ok(update.ok_or(Error::NoResponse)??),
+            Ok(_) = self.connected.wait_for(|c| !*c) => Err(Error::Disconnected),
+        }
+    }
+}
+
+// Opens links by device name or id, so long-running frontends don't care which backend they sit on.
+#[derive(Debug, Clone)]
+pub enum Connector {
+    Ble { adapter: Adapter, options: ConnectOptions },
+    Sim(crate::sim::Simulator),
+}
+
+impl Connector {
+    pub async fn connect(&self, target: &str) -> Result<Link, Error> {
+        match self {
+            Connector::Ble { adapter, options } => {
+                let device = adapter
+                    .find(target, options)
+                    .await?
+                    .ok_or_else(|| Error::NotFound(target.to_string()))?;
+                adapter.open(device, options.clone()).await
+            }
+            Connector::Sim(sim) => Ok(Link::Sim(sim.open(target, ConnectOptions::default())?)),
+        }
+    }
+}
+
+// A connected device on any of the backends, the session layer only talks to this.
+pub enum Link {
+    Default(BleLink),
+    #[cfg(target_os = "linux")]
+    Bluez(crate::bluez::BluezLink),
+    Sim(crate::sim::SimLink),
+}
+
+impl Link {
+    pub fn id(&self) -> String {
+        match self {
+            Link::Default(link) => link.device.id().to_string(),
+            #[cfg(target_os = "linux")]
+            Link::Bluez(link) => link.id(),
+            Link::Sim(link) => link.id(),
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        match self {
+            Link::Default(link) => *link.connected.borrow(),
+            #[cfg(target_os = "linux")]
+            Link::Bluez(link) => link.is_connected(),
+            Link::Sim(link) => link.is_connected(),
+        }
+    }
+
+    pub fn options(&self) -> &ConnectOptions {
+        match self {
+            Link::Default(link) => &link.options,
+            #[cfg(target_os = "linux")]
+            Link::Bluez(link) => link.options(),
+            Link::Sim(link) => link.options(),
+        }
+    }
+
+    pub async fn write_command(&self, bytes: &[u8]) -> Result<(), Error> {
+        match self {
+            Link::Default(link) => Ok(link.dev.cmd_char.write_without_response(bytes).await?),
+            #[cfg(target_os = "linux")]
+            Link::Bluez(link) => link.write_command(bytes).await,
+            Link::Sim(link) => link.write(bytes).await,
+        }
+    }
+
+    pub async fn write_data(&self, bytes: &[u8]) -> Result<(), Error> {
+        match self {
+            Link::Default(link) => Ok(link.dev.write_char.write_without_response(bytes).await?),
+            #[cfg(target_os = "linux")]
+            Link::Bluez(link) => link.write_data(bytes).await,
+            Link::Sim(link) => link.write(bytes).await,
+        }
+    }
+
+    // A device that stays connected but never answers counts as having stopped responding.
+    pub async fn notification(&mut self) -> Result<Vec<u8>, Error> {
+        let timeout = self.options().response_timeout;
+        let notification = async {
+            match self {
+                Link::Default(link) => link.notification().await,
+                #[cfg(target_os = "linux")]
+                Link::Bluez(link) => link.notification().await,
+                Link::Sim(link) => link.notification().await,
+            }
+        };
+        tokio::time::timeout(timeout, notification).await.unwrap_or(Err(Error::NoResponse))
+    }
+
+    // Writes the GAP device name, which is what the collar advertises after its next restart.
+    pub async fn rename(&self, name: &str) -> Result<(), Error> {
+        match self {
+            Link::Default(link) => Ok(link.dev.name_char.write(name.as_bytes()).await?),
+            #[cfg(target_os = "linux")]
+            Link::Bluez(link) => link.rename(name).await,
+            Link::Sim(link) => link.rename(name),
+        }
+    }
+
+    pub async fn services(&self) -> Result<Vec<GattService>, Error> {
+        match self {
+            Link::Default(link) => link.services().await,
+            #[cfg(target_os = "linux")]
+            Link::Bluez(link) => link.services().await,
+            Link::Sim(_) => Ok(crate::sim::services()),
+        }
+    }
+
+    pub async fn reconnect(&mut self) -> Result<(), Error> {
+        info!("Reconnecting to {}", self.id());
+        match self {
+            Link::Default(link) => link.reconnect().await,
+            #[cfg(target_os = "linux")]
+            Link::Bluez(link) => link.reconnect().await,
+            Link::Sim(link) => link.reconnect().await,
+        }
+    }
 }
@@ -0,0 +1,306 @@
+// Linux backend talking to bluez directly, bluest can only open the default adapter.
+use crate::{
+    ble::{
+        self, AdapterInfo, ConnectOptions, DeviceMatcher, GattCharacteristic, GattService, Search, CMD_CHARIC_UUID, NOTIFY_CHARIC_UUID,
+        WRITE_CHARIC_UUID, WRITE_SERVICE_UUID, _DEVICE_NAME_UUID, _GENERIC_SERVICE_UUID,
+    },
+    error::Error,
+};
+use bluer::{
+    Adapter, AdapterEvent, Address, Device, DeviceEvent, DeviceProperty, Session,
+    gatt::{CharacteristicFlags, WriteOp, remote::{Characteristic, CharacteristicWriteRequest}},
+};
+use bluest::Uuid;
+use log::{debug, error, info};
+use std::pin::Pin;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio_stream::{Stream, StreamExt, StreamMap};
+
+impl From<bluer::Error> for Error {
+    fn from(e: bluer::Error) -> Self {
+        Error::from(bluest::Error::from(e))
+    }
+}
+
+async fn names(session: &Session) -> Result<Vec<String>, bluer::Error> {
+    let mut names = session.adapter_names().await?;
+    names.sort();
+    Ok(names)
+}
+
+pub async fn adapters() -> Result<Vec<AdapterInfo>, Error> {
+    let session = Session::new().await?;
+    let mut infos = Vec::new();
+    for (index, name) in names(&session).await?.into_iter().enumerate() {
+        let adapter = session.adapter(&name)?;
+        infos.push(AdapterInfo {
+            index,
+            address: adapter.address().await?.to_string(),
+            available: adapter.is_powered().await?,
+            id: name,
+        });
+    }
+    Ok(infos)
+}
+
+pub async fn adapter(name: &str) -> Result<Adapter, Error> {
+    let session = Session::new().await?;
+    Ok(session.adapter(name)?)
+}
+
+pub async fn find_all(adapter: &Adapter, matcher: &DeviceMatcher, options: &ConnectOptions) -> Result<Vec<Device>, Error> {
+    let mut search = Search::new(matcher);
+    debug!("Check for connected devices on {}", adapter.name());
+    for address in adapter.device_addresses().await? {
+        let device = adapter.device(address)?;
+        // A cached device that can't be queried is skipped rather than failing the whole search.
+        match (device.is_connected().await, device.name().await) {
+            (Ok(true), Ok(name)) => {
+                search.offer(device, name, address.to_string());
+            }
+            (Ok(false), _) => {}
+            (Err(e), _) | (_, Err(e)) => debug!("Skipping {}: {}", address, e),
+        }
+    }
+    if !search.is_complete() {
+        info!("Could not find all connected devices, starting scan on {}...", adapter.name());
+        ble::until_deadline(matcher.deadline(options), scan(adapter, &mut search)).await?;
+    }
+    Ok(search.into_found())
+}
+
+type DeviceEvents = Pin<Box<dyn Stream<Item = DeviceEvent> + Send>>;
+
+// bluez reports the devices it already knows first, a device that doesn't match yet is watched
+// in case its name only comes in later.
+async fn scan(adapter: &Adapter, search: &mut Search<'_, Device>) -> Result<(), bluer::Error> {
+    let mut events = adapter.discover_devices().await?;
+    let mut watched: StreamMap<Address, DeviceEvents> = StreamMap::new();
+    loop {
+        let (address, name) = tokio::select! {
+            Some(event) = events.next() => {
+                let AdapterEvent::DeviceAdded(address) = event else {
+                    continue;
+                };
+                match adapter.device(address)?.name().await {
+                    Ok(name) => (address, name),
+                    Err(e) => {
+                        error!("Error retrieving device name: {}", e);
+                        continue;
+                    }
+                }
+            }
+            Some((address, event)) = watched.next() => {
+                let DeviceEvent::PropertyChanged(DeviceProperty::Name(name)) = event else {
+                    continue;
+                };
+                (address, Some(name))
+            }
+            else => return Ok(()),
+        };
+        let device = adapter.device(address)?;
+        if search.offer(device.clone(), name, address.to_string()) {
+            watched.remove(&address);
+            if search.is_complete() {
+                return Ok(());
+            }
+        } else if !search.has_seen(&address.to_string()) && !watched.contains_key(&address) {
+            match device.events().await {
+                Ok(device_events) => {
+                    watched.insert(address, Box::pin(device_events));
+                }
+                Err(e) => debug!("Unable to watch {}: {}", address, e),
+            }
+        }
+    }
+}
+
+pub async fn connect(device: &Device, options: &ConnectOptions) -> Result<(), bluer::Error> {
+    let what = format!("Connecting to {}", device.address());
+    ble::retry(&what, options, || device.connect()).await
+}
+
+pub fn watch_connection(device: Device) -> watch::Receiver<bool> {
+    let (tx, rx) = watch::channel(true);
+    tokio::spawn(async move {
+        let mut events = match device.events().await {
+            Ok(events) => events,
+            Err(e) => {
+                error!("Unable to watch connection events for {}: {}", device.address(), e);
+                return;
+            }
+        };
+        while let Some(DeviceEvent::PropertyChanged(property)) = events.next().await {
+            if let DeviceProperty::Connected(connected) = property {
+                debug!("Connection event for {}: {}", device.address(), connected);
+                if tx.send(connected).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    rx
+}
+
+async fn subscribe(notify_char: Characteristic) -> Result<mpsc::Receiver<Vec<u8>>, Error> {
+    let (tx, rx) = mpsc::channel(16);
+    let (ready_tx, ready_rx) = oneshot::channel();
+    tokio::spawn(async move {
+        let mut updates = match notify_char.notify().await {
+            Ok(updates) => {
+                let _ = ready_tx.send(Ok(()));
+                Box::pin(updates)
+            }
+            Err(e) => {
+                let _ = ready_tx.send(Err(e));
+                return;
+            }
+        };
+        while let Some(update) = updates.next().await {
+            if tx.send(update).await.is_err() {
+                break;
+            }
+        }
+    });
+    ready_rx.await.map_err(|_| Error::NoResponse)??;
+    Ok(rx)
+}
+
+fn properties(flags: CharacteristicFlags) -> bluest::CharacteristicProperties {
+    let bits = [
+        flags.broadcast,
+        flags.read,
+        flags.write_without_response,
+        flags.write,
+        flags.notify,
+        flags.indicate,
+        flags.authenticated_signed_writes,
+        flags.extended_properties,
+    ]
+    .iter()
+    .enumerate()
+    .fold(0, |bits, (bit, set)| bits | ((*set as u32) << bit));
+    bluest::CharacteristicProperties::from_bits(bits)
+}
+
+struct Chars {
+    cmd_char: Characteristic,
+    write_char: Characteristic,
+    notify_char: Characteristic,
+}
+
+async fn chars(device: &Device) -> Result<Chars, Error> {
+    let mut write_service = None;
+    for service in device.services().await? {
+        if service.uuid().await? == WRITE_SERVICE_UUID {
+            write_service = Some(service);
+        }
+    }
+    let write_service = write_service.ok_or(bluest::error::ErrorKind::NotFound)?;
+    let mut found: Vec<(Uuid, Characteristic)> = Vec::new();
+    for charic in write_service.characteristics().await? {
+        found.push((charic.uuid().await?, charic));
+    }
+    let find_char = |uuid: Uuid| {
+        found
+            .iter()
+            .find(|(u, _)| *u == uuid)
+            .map(|(_, c)| c.clone())
+            .ok_or(bluest::error::ErrorKind::NotFound)
+    };
+    Ok(Chars {
+        cmd_char: find_char(CMD_CHARIC_UUID)?,
+        write_char: find_char(WRITE_CHARIC_UUID)?,
+        notify_char: find_char(NOTIFY_CHARIC_UUID)?,
+    })
+}
+
+pub struct BluezLink {
+    device: Device,
+    chars: Chars,
+    updates: mpsc::Receiver<Vec<u8>>,
+    connected: watch::Receiver<bool>,
+    options: ConnectOptions,
+}
+
+fn without_response() -> CharacteristicWriteRequest {
+    CharacteristicWriteRequest {
+        op_type: WriteOp::Command,
+        ..Default::default()
+    }
+}
+
+impl BluezLink {
+    pub async fn open(device: Device, options: ConnectOptions) -> Result<Self, Error> {
+        let connected = watch_connection(device.clone());
+        let chars = chars(&device).await?;
+        debug!("Subscribing to notifications...");
+        let updates = subscribe(chars.notify_char.clone()).await?;
+        Ok(BluezLink { device, chars, updates, connected, options })
+    }
+
+    pub fn id(&self) -> String {
+        self.device.address().to_string()
+    }
+
+    pub fn options(&self) -> &ConnectOptions {
+        &self.options
+    }
+
+    pub fn is_connected(&self) -> bool {
+        *self.connected.borrow()
+    }
+
+    pub async fn write_command(&self, bytes: &[u8]) -> Result<(), Error> {
+        Ok(self.chars.cmd_char.write_ext(bytes, &without_response()).await?)
+    }
+
+    pub async fn write_data(&self, bytes: &[u8]) -> Result<(), Error> {
+        Ok(self.chars.write_char.write_ext(bytes, &without_response()).await?)
+    }
+
+    pub async fn notification(&mut self) -> Result<Vec<u8>, Error> {
+        tokio::select! {
+            update = self.updates.recv() => update.ok_or(Error::NoResponse),
+            Ok(_) = self.connected.wait_for(|c| !*c) => Err(Error::Disconnected),
+        }
+    }
+
+    // bluez keeps the GAP service to itself on most setups, in which case renaming isn't possible from here.
+    pub async fn rename(&self, name: &str) -> Result<(), Error> {
+        for service in self.device.services().await? {
+            if service.uuid().await? != _GENERIC_SERVICE_UUID {
+                continue;
+            }
+            for charic in service.characteristics().await? {
+                if charic.uuid().await? == _DEVICE_NAME_UUID {
+                    return Ok(charic.write(name.as_bytes()).await?);
+                }
+            }
+        }
+        Err(bluest::error::ErrorKind::NotSupported.into())
+    }
+
+    pub async fn services(&self) -> Result<Vec<GattService>, Error> {
+        let mut services = Vec::new();
+        for service in self.device.services().await? {
+            let mut characteristics = Vec::new();
+            for charic in service.characteristics().await? {
+                characteristics.push(GattCharacteristic {
+                    uuid: charic.uuid().await?,
+                    properties: ble::property_names(properties(charic.flags().await?)),
+                });
+            }
+            services.push(GattService { uuid: service.uuid().await?, characteristics });
+        }
+        Ok(services)
+    }
+
+    pub async fn reconnect(&mut self) -> Result<(), Error> {
+        connect(&self.device, &self.options).await?;
+        self.connected = watch_connection(self.device.clone());
+        self.chars = chars(&self.device).await?;
+        self.updates = subscribe(self.chars.notify_char.clone()).await?;
+        Ok(())
+    }
+}
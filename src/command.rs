@@ -0,0 +1,148 @@
+// Commands a long-running frontend runs against an open session, shared by iledd's JSON requests and --stdin lines.
+//
+// color red
+// color #ff8800
+// image path/to/design.gif
+// brightness 3
+// off
+// password change 123456 654321
+use crate::{
+    color::Color,
+    config::Panel,
+    error::Error,
+    fit::{Fit, FitOptions},
+    image::ILedImage,
+    packet::{CtnData, Password, PasswordOp},
+    send::Session,
+};
+use serde::Deserialize;
+use std::{path::PathBuf, str::FromStr, sync::Arc};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    Image {
+        path: PathBuf,
+        #[serde(default)]
+        fit: Fit,
+    },
+    Color { rgb: [u8; 3] },
+    Brightness { level: u8 },
+    Enable { on: bool },
+    // old only: unset, new only: set, both: change
+    Password { old: Option<String>, new: Option<String> },
+    List,
+    // Image data that's already encoded, for frontends that receive the file itself rather than a path.
+    #[serde(skip)]
+    Upload(Arc<CtnData>),
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let line = line.trim();
+        let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let args: Vec<&str> = rest.split_whitespace().collect();
+        match (word, args.as_slice()) {
+            ("image", _) if !rest.is_empty() => Ok(Command::Image { path: PathBuf::from(rest), fit: Fit::default() }),
+            ("color", _) if !rest.is_empty() => Ok(Command::Color { rgb: rest.parse::<Color>()?.rgb() }),
+            ("brightness", [level]) => level
+                .parse()
+                .map(|level| Command::Brightness { level })
+                .map_err(|_| format!("invalid brightness: {}", level)),
+            ("on", []) => Ok(Command::Enable { on: true }),
+            ("off", []) => Ok(Command::Enable { on: false }),
+            ("password", ["set", new]) => Ok(Command::Password { old: None, new: Some(new.to_string()) }),
+            ("password", ["change", old, new]) => Ok(Command::Password { old: Some(old.to_string()), new: Some(new.to_string()) }),
+            ("password", ["unset", old]) => Ok(Command::Password { old: Some(old.to_string()), new: None }),
+            ("image" | "color" | "brightness" | "on" | "off" | "password", _) => Err(format!("bad arguments for {}", word)),
+            _ => Err(format!("unknown command: {}", word)),
+        }
+    }
+}
+
+fn parse_password(password: Option<&str>) -> Result<Option<Password>, Error> {
+    password
+        .map(|p| p.parse().map_err(Error::Config))
+        .transpose()
+}
+
+fn password_op(old: Option<&str>, new: Option<&str>) -> Result<PasswordOp, Error> {
+    match (parse_password(old)?, parse_password(new)?) {
+        (None, Some(new)) => Ok(PasswordOp::Set(new)),
+        (Some(old), Some(new)) => Ok(PasswordOp::Change { old, new }),
+        (Some(old), None) => Ok(PasswordOp::Unset(old)),
+        (None, None) => Err(Error::Config("password needs old, new or both".to_string())),
+    }
+}
+
+// Solid colours are drawn for `panel` and image files or directories of frames fitted to it.
+pub async fn execute(session: &mut Session, panel: Panel, command: &Command) -> Result<(), Error> {
+    match command {
+        Command::Image { path, fit } => {
+            // decoding and resizing would hold up every other session on the runtime
+            let (path, fit) = (path.clone(), *fit);
+            let image = tokio::task::spawn_blocking(move || {
+                ILedImage::from_path(&path, panel, &FitOptions::new(fit))
+                    .map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))
+            })
+            .await
+            .map_err(|e| Error::Config(e.to_string()))??;
+            session.upload(&CtnData::new(image.to_bytes())).await
+        }
+        Command::Color { rgb: [r, g, b] } => {
+            let image = ILedImage::solid_color(panel, Color::new(*r, *g, *b), &FitOptions::default());
+            session.upload(&CtnData::new(image.to_bytes())).await
+        }
+        Command::Brightness { level } if *level > 10 => Err(Error::Config(format!(
+            "brightness must be 0 (brightest) to 10 (dimmest), got {}",
+            level
+        ))),
+        Command::Brightness { level } => session.brightness(*level).await,
+        Command::Enable { on } => session.enable(*on).await,
+        Command::Password { old, new } => session.password(password_op(old.as_deref(), new.as_deref())?).await,
+        Command::Upload(data) => session.upload(data).await,
+        Command::List => Ok(()),
+    }
+}
+
+// Runs one command per input line on the same session and answers each with "ok" or "error: ...".
+// Blank lines and lines starting with '#' are skipped. Returns how many commands failed.
+pub async fn run_lines<R, W>(session: &mut Session, panel: Panel, input: R, mut output: W) -> std::io::Result<usize>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut failed = 0;
+    let mut lines = input.lines();
+    while let Some(line) = lines.next_line().await? {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let result = match line.parse::<Command>() {
+            Ok(command) => async {
+                if !session.is_connected() {
+                    session.reconnect().await?;
+                }
+                execute(session, panel, &command).await
+            }
+            .await
+            .map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
+        let reply = match result {
+            Ok(()) => "ok\n".to_string(),
+            Err(e) => {
+                failed += 1;
+                format!("error: {}\n", e)
+            }
+        };
+        output.write_all(reply.as_bytes()).await?;
+        output.flush().await?;
+    }
+    Ok(failed)
+}
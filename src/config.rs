@@ -0,0 +1,194 @@
+use crate::{ble::DeviceMatcher, error::Error, packet::Password, profile::PanelProfile};
+use serde::Deserialize;
+use std::{collections::HashSet, fs, path::Path, str::FromStr};
+
+// [[device]]
+// alias = "rex"
+// name = "iLedColor-1A2B"     # or id = "9E:19:3D:7C:21:BE"
+// password = "123456"
+// brightness = 3
+// panel = { width = 48, height = 12 }   # or profile = "collar"
+// groups = ["pack"]
+//
+// or the same as YAML, in a .yaml or .yml file:
+//
+// device:
+//   - alias: rex
+//     name: iLedColor-1A2B
+//     panel: { width: 48, height: 12 }
+//     groups: [pack]
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Panel {
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Default for Panel {
+    fn default() -> Self {
+        Panel { width: 48, height: 12 }
+    }
+}
+
+// WIDTHxHEIGHT, e.g. 48x12
+impl FromStr for Panel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = s
+            .split_once(['x', 'X'])
+            .ok_or_else(|| format!("panel size must be WIDTHxHEIGHT, got {}", s))?;
+        let size = |v: &str| match v.trim().parse::<u16>() {
+            Ok(v) if v > 0 => Ok(v),
+            _ => Err(format!("invalid panel size {}", s)),
+        };
+        Ok(Panel { width: size(width)?, height: size(height)? })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceEntry {
+    pub alias: String,
+    pub name: Option<String>,
+    pub id: Option<String>,
+    password: Option<String>,
+    pub brightness: Option<u8>,
+    #[serde(default)]
+    pub panel: Panel, // set from the profile when there is one
+    profile: Option<String>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+impl DeviceEntry {
+    pub fn password(&self) -> Option<Password> {
+        self.password.as_deref().map(|p| p.parse().expect("validated on load"))
+    }
+
+    pub fn profile(&self) -> PanelProfile {
+        match &self.profile {
+            Some(name) => PanelProfile::named(name).expect("validated on load"),
+            None => PanelProfile::for_panel(self.panel),
+        }
+    }
+
+    // What the device is looked up by while scanning, the id wins when both are given.
+    pub fn target(&self) -> &str {
+        self.id
+            .as_deref()
+            .or(self.name.as_deref())
+            .expect("validated on load")
+    }
+
+    pub fn matches(&self, name: Option<&str>, id: &str) -> bool {
+        DeviceMatcher::Names(vec![self.target().to_string()]).matches(name, id)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.alias.is_empty() {
+            return Err("device alias can't be empty".to_string());
+        }
+        if self.name.is_none() && self.id.is_none() {
+            return Err(format!("device {} needs a name or an id", self.alias));
+        }
+        if let Some(password) = &self.password {
+            password
+                .parse::<Password>()
+                .map_err(|e| format!("device {}: {}", self.alias, e))?;
+        }
+        if let Some(brightness) = self.brightness
+            && brightness > 10
+        {
+            return Err(format!("device {}: brightness must be 0 (brightest) to 10 (dimmest), got {}", self.alias, brightness));
+        }
+        if self.panel.width == 0 || self.panel.height == 0 {
+            return Err(format!("device {}: panel size can't be zero", self.alias));
+        }
+        if let Some(name) = &self.profile {
+            let profile = PanelProfile::named(name).ok_or_else(|| format!("device {}: unknown profile {}", self.alias, name))?;
+            if self.panel != Panel::default() && self.panel != profile.panel {
+                return Err(format!("device {}: give a panel or a profile, not both", self.alias));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Inventory {
+    #[serde(default, rename = "device")]
+    pub devices: Vec<DeviceEntry>,
+}
+
+impl Inventory {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))?;
+        let yaml = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"));
+        let inventory = if yaml { Self::parse_yaml(&text) } else { Self::parse(&text) };
+        inventory.map_err(|e| match e {
+            Error::Config(message) => Error::Config(format!("{}: {}", path.display(), message)),
+            e => e,
+        })
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        toml::from_str::<Inventory>(text)
+            .map_err(|e| Error::Config(e.to_string()))?
+            .checked()
+    }
+
+    pub fn parse_yaml(text: &str) -> Result<Self, Error> {
+        serde_yaml_ng::from_str::<Inventory>(text)
+            .map_err(|e| Error::Config(e.to_string()))?
+            .checked()
+    }
+
+    fn checked(mut self) -> Result<Self, Error> {
+        self.validate().map_err(Error::Config)?;
+        for device in &mut self.devices {
+            device.panel = device.profile().panel;
+        }
+        Ok(self)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let mut aliases = HashSet::new();
+        for device in &self.devices {
+            device.validate()?;
+            if !aliases.insert(device.alias.as_str()) {
+                return Err(format!("device alias {} is used more than once", device.alias));
+            }
+        }
+        for group in self.devices.iter().flat_map(|d| &d.groups) {
+            if aliases.contains(group.as_str()) {
+                return Err(format!("group {} has the same name as a device alias", group));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn device(&self, alias: &str) -> Result<&DeviceEntry, Error> {
+        self.devices
+            .iter()
+            .find(|d| d.alias == alias)
+            .ok_or_else(|| Error::Config(format!("no device with alias {}", alias)))
+    }
+
+    pub fn group(&self, group: &str) -> Result<Vec<&DeviceEntry>, Error> {
+        let members: Vec<_> = self
+            .devices
+            .iter()
+            .filter(|d| d.groups.iter().any(|g| g == group))
+            .collect();
+        if members.is_empty() {
+            return Err(Error::Config(format!("no devices in group {}", group)));
+        }
+        Ok(members)
+    }
+}
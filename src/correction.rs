@@ -0,0 +1,49 @@
+// LEDs are linear and their channels differ in strength, so pixels meant for an sRGB screen come out washed out and
+// tinted. Corrections apply to the panel sized frames, in order: gamma, white balance gains, then the power limit.
+use image::RgbImage;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Correction {
+    pub gamma: f32,             // 1 leaves levels alone, around 2.2 undoes sRGB encoding
+    pub gain: [f32; 3],         // per channel multipliers for white balance
+    pub max_power: Option<f32>, // cap on a frame's total channel sum, as a fraction of every pixel full white
+}
+
+impl Default for Correction {
+    fn default() -> Self {
+        Correction { gamma: 1.0, gain: [1.0; 3], max_power: None }
+    }
+}
+
+impl Correction {
+    pub fn is_identity(&self) -> bool {
+        *self == Correction::default()
+    }
+
+    pub fn apply(&self, image: &mut RgbImage) {
+        if self.is_identity() {
+            return;
+        }
+        let tables: [[u8; 256]; 3] = std::array::from_fn(|channel| {
+            std::array::from_fn(|level| {
+                let linear = (level as f32 / 255.0).powf(self.gamma);
+                (linear * self.gain[channel] * 255.0).round().clamp(0.0, 255.0) as u8
+            })
+        });
+        for pixel in image.pixels_mut() {
+            for (channel, value) in pixel.0.iter_mut().enumerate() {
+                *value = tables[channel][*value as usize];
+            }
+        }
+        if let Some(max_power) = self.max_power {
+            let budget = max_power as f64 * 255.0 * image.as_raw().len() as f64;
+            let total: u64 = image.as_raw().iter().map(|&value| value as u64).sum();
+            if total as f64 > budget {
+                let scale = budget / total as f64;
+                for value in image.iter_mut() {
+                    *value = (*value as f64 * scale) as u8;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,276 @@
+// iledd: keeps a session per configured device and serves newline-delimited JSON over a unix socket (and HTTP, see http.rs).
+//
+// -> {"device": "rex", "command": "brightness", "level": 3}
+// <- {"ok": true}
+// -> {"command": "list"}
+// <- {"ok": true, "devices": [{"alias": "rex", "connected": true, "queued": 0}]}
+use crate::{
+    ble::Connector,
+    command::{Command, execute},
+    config::{DeviceEntry, Inventory, Panel},
+    error::{Error, ErrorKind},
+    packet::Password,
+    send::Session,
+};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+use tokio::sync::{mpsc, oneshot, watch};
+#[cfg(unix)]
+use {
+    std::path::Path,
+    tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::UnixListener,
+    },
+};
+
+// How often an idle worker pings its device, so links that dropped or went half-open are noticed and reconnected.
+const KEEPALIVE: Duration = Duration::from_secs(10);
+const QUEUE_LEN: usize = 32;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Request {
+    pub device: Option<String>,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub devices: Vec<DeviceStatus>,
+    // what went wrong when the error came from running the command, for frontends that answer with status codes
+    #[serde(skip)]
+    pub kind: Option<ErrorKind>,
+}
+
+impl Response {
+    fn ok() -> Self {
+        Response { ok: true, ..Default::default() }
+    }
+
+    pub fn error(e: impl ToString) -> Self {
+        Response { ok: false, error: Some(e.to_string()), ..Default::default() }
+    }
+}
+
+impl From<Result<(), Error>> for Response {
+    fn from(result: Result<(), Error>) -> Self {
+        match result {
+            Ok(()) => Response::ok(),
+            Err(e) => Response { kind: Some(e.kind()), ..Response::error(e) },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceStatus {
+    pub alias: String,
+    pub connected: bool,
+    pub queued: usize,
+}
+
+type Job = (Command, oneshot::Sender<Response>);
+
+struct Worker {
+    panel: Panel,
+    queue: mpsc::Sender<Job>,
+    connected: watch::Receiver<bool>,
+    queued: Arc<AtomicUsize>,
+}
+
+pub struct Daemon {
+    workers: BTreeMap<String, Worker>,
+}
+
+async fn connect(connector: &Connector, entry: &DeviceEntry, password: Option<Password>) -> Result<Session, Error> {
+    let link = connector.connect(entry.target()).await?;
+    let mut session = Session::open(link, password).await?;
+    session.set_profile(entry.profile());
+    if let Some(level) = entry.brightness {
+        session.brightness(level).await?;
+    }
+    Ok(session)
+}
+
+// Owns the device's session and runs its commands one at a time, reconnecting whenever it finds the link down.
+// A password changed through the daemon replaces the configured one for every later connection.
+async fn run(entry: DeviceEntry, connector: Connector, mut queue: mpsc::Receiver<Job>, connected: watch::Sender<bool>, queued: Arc<AtomicUsize>) {
+    let mut session: Option<Session> = None;
+    let mut password = entry.password();
+    let mut keepalive = tokio::time::interval(KEEPALIVE);
+    loop {
+        let job = tokio::select! {
+            job = queue.recv() => match job {
+                Some(job) => Some(job),
+                None => break,
+            },
+            _ = keepalive.tick() => None,
+        };
+
+        if let Some(current) = &mut session
+            && !current.is_connected()
+            && let Err(e) = current.reconnect().await
+        {
+            warn!("{}: reconnect failed: {}", entry.alias, e);
+            session = None;
+        }
+        if session.is_none() {
+            match connect(&connector, &entry, password).await {
+                Ok(new) => {
+                    info!("{}: connected to {}", entry.alias, new.id());
+                    session = Some(new);
+                }
+                Err(e) => {
+                    warn!("{}: connecting failed: {}", entry.alias, e);
+                    if let Some((_, reply)) = job {
+                        queued.fetch_sub(1, Ordering::SeqCst);
+                        let _ = reply.send(Err(e).into());
+                    }
+                    let _ = connected.send(false);
+                    continue;
+                }
+            }
+        }
+
+        let current = session.as_mut().expect("connected above");
+        let lost = match job {
+            Some((command, reply)) => {
+                let result = execute(current, entry.panel, &command).await;
+                if let (Command::Password { .. }, Ok(())) = (&command, &result) {
+                    password = current.password_in_use();
+                }
+                let lost = matches!(result, Err(Error::Disconnected | Error::NoResponse));
+                queued.fetch_sub(1, Ordering::SeqCst);
+                let _ = reply.send(result.into());
+                lost
+            }
+            None => match current.ping().await {
+                Ok(()) => false,
+                Err(e) => {
+                    warn!("{}: keepalive failed: {}", entry.alias, e);
+                    true
+                }
+            },
+        };
+        if lost {
+            session = None;
+        }
+        let _ = connected.send(session.as_ref().is_some_and(Session::is_connected));
+    }
+}
+
+impl Daemon {
+    // Spawns a worker per inventory device, they connect right away and keep retrying in the background.
+    pub fn new(inventory: &Inventory, connector: Connector) -> Self {
+        let mut workers = BTreeMap::new();
+        for entry in &inventory.devices {
+            let (queue, rx) = mpsc::channel(QUEUE_LEN);
+            let (connected_tx, connected) = watch::channel(false);
+            let queued = Arc::new(AtomicUsize::new(0));
+            tokio::spawn(run(entry.clone(), connector.clone(), rx, connected_tx, queued.clone()));
+            workers.insert(entry.alias.clone(), Worker { panel: entry.panel, queue, connected, queued });
+        }
+        Daemon { workers }
+    }
+
+    pub fn status(&self) -> Vec<DeviceStatus> {
+        self.workers
+            .iter()
+            .map(|(alias, worker)| DeviceStatus {
+                alias: alias.clone(),
+                connected: *worker.connected.borrow(),
+                queued: worker.queued.load(Ordering::SeqCst),
+            })
+            .collect()
+    }
+
+    pub fn contains(&self, alias: &str) -> bool {
+        self.workers.contains_key(alias)
+    }
+
+    pub fn panel(&self, alias: &str) -> Option<Panel> {
+        self.workers.get(alias).map(|worker| worker.panel)
+    }
+
+    pub async fn handle(&self, request: Request) -> Response {
+        if let Command::List = request.command {
+            return Response { devices: self.status(), ..Response::ok() };
+        }
+        let Some(alias) = request.device else {
+            return Response::error("missing device");
+        };
+        let Some(worker) = self.workers.get(&alias) else {
+            return Response::error(format!("no device with alias {}", alias));
+        };
+        let (reply, response) = oneshot::channel();
+        worker.queued.fetch_add(1, Ordering::SeqCst);
+        if worker.queue.send((request.command, reply)).await.is_err() {
+            worker.queued.fetch_sub(1, Ordering::SeqCst);
+            return Response::error("device worker stopped");
+        }
+        response.await.unwrap_or_else(|_| Response::error("device worker stopped"))
+    }
+
+    pub async fn handle_line(&self, line: &str) -> Response {
+        match serde_json::from_str(line) {
+            Ok(request) => self.handle(request).await,
+            Err(e) => Response::error(format!("invalid request: {}", e)),
+        }
+    }
+
+    // Each connection is answered in order, one response line per request line.
+    #[cfg(unix)]
+    pub async fn serve(self: Arc<Self>, listener: UnixListener) -> std::io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let daemon = self.clone();
+            tokio::spawn(async move {
+                let (read, mut write) = stream.into_split();
+                let mut lines = BufReader::new(read).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let response = daemon.handle_line(&line).await;
+                    let mut out = serde_json::to_string(&response).expect("responses always serialize");
+                    out.push('\n');
+                    if write.write_all(out.as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    }
+}
+
+// Binds the socket, replacing a stale one left behind by an earlier run but never one another daemon still listens on.
+#[cfg(unix)]
+pub fn bind(path: &Path) -> std::io::Result<UnixListener> {
+    use std::{io::ErrorKind, os::unix::fs::FileTypeExt};
+
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(std::io::Error::new(ErrorKind::AlreadyExists, format!("{} exists and is not a socket", path.display())));
+        }
+        match std::os::unix::net::UnixStream::connect(path) {
+            Ok(_) => {
+                return Err(std::io::Error::new(ErrorKind::AddrInUse, format!("another daemon is listening on {}", path.display())));
+            }
+            Err(e) if e.kind() == ErrorKind::ConnectionRefused => std::fs::remove_file(path)?,
+            Err(e) => return Err(e),
+        }
+    }
+    UnixListener::bind(path)
+}
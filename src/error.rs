@@ -0,0 +1,75 @@
+use crate::packet::Handle;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    Bluetooth(bluest::Error),
+    Disconnected,
+    NoResponse,
+    BadNotification(String, Vec<u8>),
+    NoAdapter(String),
+    NotFound(String),
+    WrongPassword,
+    Rejected(Handle),
+    Config(String),
+    TooLarge(usize, usize),
+}
+
+// Whose fault a failure is, frontends turn it into a status code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    Invalid,  // the request itself, sending it again won't help
+    Device,   // the device or the bluetooth stack
+    Timeout,  // the device stopped answering
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Config(_) | Error::TooLarge(..) => ErrorKind::Invalid,
+            Error::NoResponse => ErrorKind::Timeout,
+            _ => ErrorKind::Device,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Bluetooth(e) => write!(f, "bluetooth error: {}", e),
+            Error::Disconnected => write!(f, "device disconnected"),
+            Error::NoResponse => write!(f, "device stopped sending notifications"),
+            Error::BadNotification(message, bytes) => write!(f, "garbled notification {:02x?}: {}", bytes, message),
+            Error::NoAdapter(selector) => write!(f, "no usable bluetooth adapter: {}", selector),
+            Error::NotFound(device) => write!(f, "device {} not found", device),
+            Error::WrongPassword => write!(f, "device rejected the password"),
+            Error::Rejected(handle) => write!(f, "device rejected the {} command", handle),
+            Error::Config(message) => write!(f, "invalid config: {}", message),
+            Error::TooLarge(len, max) => write!(f, "{} byte upload is more than the device takes ({})", len, max),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Bluetooth(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<bluest::Error> for Error {
+    fn from(e: bluest::Error) -> Self {
+        match e.kind() {
+            bluest::error::ErrorKind::NotConnected => Error::Disconnected,
+            _ => Error::Bluetooth(e),
+        }
+    }
+}
+
+impl From<bluest::error::ErrorKind> for Error {
+    fn from(kind: bluest::error::ErrorKind) -> Self {
+        Error::from(bluest::Error::from(kind))
+    }
+}
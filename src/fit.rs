@@ -0,0 +1,127 @@
+// Brings an image of any size to the panel's resolution, the device shows nothing useful for anything else.
+use crate::{color::Color, config::Panel, correction::Correction, quantise::Quantise};
+use image::{Rgb, RgbImage, RgbaImage, imageops::{self, FilterType}};
+use serde::Deserialize;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Fit {
+    // scaled to the panel, ignoring the aspect ratio
+    Stretch,
+    // scaled to fit inside the panel, the uncovered borders filled with the letterbox colour
+    #[default]
+    Contain,
+    // scaled to fill the panel, whatever sticks out is cropped equally from both sides
+    Cover,
+    // not scaled, centred and cropped or bordered as needed
+    Center,
+    // not scaled, repeated from the top left corner
+    Tile,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Filter {
+    // keeps hard pixel edges, for pixel art
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    #[default]
+    Lanczos3,
+}
+
+impl From<Filter> for FilterType {
+    fn from(filter: Filter) -> Self {
+        match filter {
+            Filter::Nearest => FilterType::Nearest,
+            Filter::Triangle => FilterType::Triangle,
+            Filter::CatmullRom => FilterType::CatmullRom,
+            Filter::Gaussian => FilterType::Gaussian,
+            Filter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FitOptions {
+    pub fit: Fit,
+    pub filter: Filter,
+    pub letterbox: Color, // fills whatever the image doesn't cover, transparent pixels included
+    pub correction: Correction, // applied once the image has the panel's size
+    pub quantise: Quantise, // applied last, after the correction
+}
+
+impl FitOptions {
+    pub fn new(fit: Fit) -> Self {
+        FitOptions { fit, ..Default::default() }
+    }
+
+    // Whether pixels already at the panel's size still change.
+    pub fn changes_colours(&self) -> bool {
+        !self.correction.is_identity() || !self.quantise.is_identity()
+    }
+}
+
+// Blends transparent pixels into `background`, the panel has nothing to show through them.
+pub fn flatten(image: &RgbaImage, background: Color) -> RgbImage {
+    let background = background.rgb();
+    RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let [r, g, b, a] = image.get_pixel(x, y).0;
+        let blend = |c: u8, bg: u8| ((c as u32 * a as u32 + bg as u32 * (255 - a as u32) + 127) / 255) as u8;
+        Rgb([blend(r, background[0]), blend(g, background[1]), blend(b, background[2])])
+    })
+}
+
+// Draws `image` onto a letterbox coloured canvas the size of the panel, its top left corner at (x, y).
+fn place(image: &RgbImage, panel: Panel, x: i64, y: i64, letterbox: Color) -> RgbImage {
+    let mut canvas = RgbImage::from_pixel(panel.width as u32, panel.height as u32, Rgb(letterbox.rgb()));
+    imageops::replace(&mut canvas, image, x, y);
+    canvas
+}
+
+// Scales `image` to `width` by `height`, returning it untouched when it already has that size.
+fn scale(image: &RgbImage, width: u32, height: u32, filter: Filter) -> RgbImage {
+    if image.dimensions() == (width, height) {
+        return image.clone();
+    }
+    imageops::resize(image, width.max(1), height.max(1), filter.into())
+}
+
+// The image at exactly the panel's width and height, colour corrected for the LEDs and reduced to the colours
+// the upload asks for.
+pub fn fit(image: &RgbImage, panel: Panel, options: &FitOptions) -> RgbImage {
+    let mut fitted = resize(image, panel, options);
+    options.correction.apply(&mut fitted);
+    options.quantise.apply(&mut fitted);
+    fitted
+}
+
+fn resize(image: &RgbImage, panel: Panel, options: &FitOptions) -> RgbImage {
+    let (width, height) = (panel.width as u32, panel.height as u32);
+    let (source_width, source_height) = (image.width() as f64, image.height() as f64);
+    let centred = |scaled: &RgbImage| {
+        let x = (width as i64 - scaled.width() as i64) / 2;
+        let y = (height as i64 - scaled.height() as i64) / 2;
+        place(scaled, panel, x, y, options.letterbox)
+    };
+    match options.fit {
+        Fit::Stretch => scale(image, width, height, options.filter),
+        Fit::Contain | Fit::Cover => {
+            let ratios = (width as f64 / source_width, height as f64 / source_height);
+            let ratio = match options.fit {
+                Fit::Contain => ratios.0.min(ratios.1),
+                _ => ratios.0.max(ratios.1),
+            };
+            let scaled = scale(
+                image,
+                (source_width * ratio).round() as u32,
+                (source_height * ratio).round() as u32,
+                options.filter,
+            );
+            centred(&scaled)
+        }
+        Fit::Center => centred(image),
+        Fit::Tile => RgbImage::from_fn(width, height, |x, y| *image.get_pixel(x % image.width(), y % image.height())),
+    }
+}
@@ -0,0 +1,118 @@
+use crate::{
+    ble::{Adapter, ConnectOptions, Device},
+    command::{self, Command},
+    error::Error,
+    packet::Password,
+    profile::PanelProfile,
+    send::Session,
+};
+use log::warn;
+use serde::{Serialize, Serializer, ser::SerializeStruct};
+use std::{fmt, sync::Arc};
+use tokio::{sync::Semaphore, task::JoinSet};
+
+#[derive(Debug, Clone)]
+pub struct FleetOptions {
+    pub parallel: usize, // sessions running at once
+    pub retries: u32,    // whole jobs repeated per device, on top of the per-connection retries
+}
+
+impl Default for FleetOptions {
+    fn default() -> Self {
+        FleetOptions {
+            parallel: 4,
+            retries: 1,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Report {
+    pub name: Option<String>,
+    pub id: String,
+    pub attempts: u32,
+    pub result: Result<(), Error>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}]: ", self.name.as_deref().unwrap_or("(unknown)"), self.id)?;
+        match &self.result {
+            Ok(()) => write!(f, "ok after {} attempt(s)", self.attempts),
+            Err(e) => write!(f, "failed after {} attempt(s): {}", self.attempts, e),
+        }
+    }
+}
+
+impl Serialize for Report {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut report = serializer.serialize_struct("Report", 5)?;
+        report.serialize_field("name", &self.name)?;
+        report.serialize_field("id", &self.id)?;
+        report.serialize_field("attempts", &self.attempts)?;
+        report.serialize_field("ok", &self.result.is_ok())?;
+        report.serialize_field("error", &self.result.as_ref().err().map(|e| e.to_string()))?;
+        report.end()
+    }
+}
+
+// One device's commands, run in order on a single session.
+// Jobs sending the same design should share its data through `Command::Upload`.
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub device: Device,
+    pub password: Option<Password>,
+    pub profile: PanelProfile, // what colours are drawn for and what uploads are checked against
+    pub commands: Vec<Command>,
+}
+
+async fn run_one(adapter: &Adapter, job: &Job, options: ConnectOptions) -> Result<(), Error> {
+    let link = adapter.open(job.device.clone(), options).await?;
+    let mut session = Session::open(link, job.password).await?;
+    // logs devices whose handshake doesn't fit the profile picked from their name
+    session.set_profile(job.profile);
+    for command in &job.commands {
+        command::execute(&mut session, job.profile.panel, command).await?;
+    }
+    Ok(())
+}
+
+// Runs every job with at most `parallel` sessions open at a time.
+// Reports come back in completion order.
+pub async fn run(
+    adapter: &Adapter,
+    jobs: Vec<Job>,
+    options: &ConnectOptions,
+    fleet: &FleetOptions,
+) -> Vec<Report> {
+    let permits = Arc::new(Semaphore::new(fleet.parallel.max(1)));
+    let mut sessions = JoinSet::new();
+    for job in jobs {
+        let adapter = adapter.clone();
+        let options = options.clone();
+        let permits = permits.clone();
+        let retries = fleet.retries;
+        sessions.spawn(async move {
+            let _permit = permits.acquire_owned().await.expect("semaphore is never closed");
+            let name = job.device.name().await;
+            let id = job.device.id();
+            let mut attempts = 0;
+            let result = loop {
+                attempts += 1;
+                match run_one(&adapter, &job, options.clone()).await {
+                    Err(e) if attempts <= retries => {
+                        warn!("{} failed ({}), retry {}/{}", id, e, attempts, retries);
+                    }
+                    result => break result,
+                }
+            };
+            Report { name, id, attempts, result }
+        });
+    }
+
+    let mut reports = Vec::new();
+    while let Some(report) = sessions.join_next().await {
+        reports.push(report.expect("fleet task panicked"));
+    }
+    reports
+}
@@ -0,0 +1,147 @@
+// TrueType/OpenType text. Glyphs are rasterised with anti-aliasing and then thresholded or dithered, since an LED is
+// either on or off at low brightness. Characters missing from the first font are looked up in the fallbacks, in order.
+use crate::quantise::BAYER;
+use ab_glyph::{Font, FontVec, GlyphId, GlyphImageFormat, PxScale, ScaleFont, point};
+use image::{GrayImage, Luma, imageops::FilterType};
+use std::{fs, io, path::Path};
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Smoothing {
+    // lit where a pixel is at least half covered
+    #[default]
+    Threshold,
+    // ordered dithering, keeps some of the shape of thin strokes and curves
+    Dither,
+    // keep the coverage as it is, blended between the foreground and background colours
+    Antialias,
+}
+
+pub struct OutlineFont {
+    fonts: Vec<FontVec>,
+    pub size: f32, // pixels from the highest ascender to the lowest descender
+    pub smoothing: Smoothing,
+}
+
+struct Placed {
+    font: usize,
+    id: GlyphId,
+    x: f32,
+}
+
+impl OutlineFont {
+    // The first font is the main one, the rest are fallbacks for the characters it lacks.
+    pub fn new(fonts: Vec<FontVec>, size: f32, smoothing: Smoothing) -> Self {
+        assert!(!fonts.is_empty(), "at least one font is needed");
+        OutlineFont { fonts, size, smoothing }
+    }
+
+    pub fn load(paths: &[impl AsRef<Path>], size: f32, smoothing: Smoothing) -> io::Result<Self> {
+        if paths.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no font given"));
+        }
+        let mut fonts = Vec::new();
+        for path in paths {
+            let path = path.as_ref();
+            let data = fs::read(path).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+            let font = FontVec::try_from_vec(data)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))?;
+            fonts.push(font);
+        }
+        Ok(Self::new(fonts, size, smoothing))
+    }
+
+    // The first font with a glyph for `c`, or the main font's placeholder box when none has one.
+    fn pick(&self, c: char) -> (usize, GlyphId) {
+        self.fonts
+            .iter()
+            .enumerate()
+            .map(|(index, font)| (index, font.glyph_id(c)))
+            .find(|(_, id)| id.0 != 0)
+            .unwrap_or((0, GlyphId(0)))
+    }
+
+    fn layout(&self, text: &str) -> (Vec<Placed>, f32) {
+        let scale = PxScale::from(self.size);
+        let mut placed: Vec<Placed> = Vec::new();
+        let mut pen = 0.0;
+        for c in text.chars().filter(|c| !c.is_control()) {
+            let (font, id) = self.pick(c);
+            let scaled = self.fonts[font].as_scaled(scale);
+            if let Some(previous) = placed.last().filter(|p| p.font == font) {
+                pen += scaled.kern(previous.id, id);
+            }
+            placed.push(Placed { font, id, x: pen });
+            pen += scaled.h_advance(id);
+        }
+        (placed, pen)
+    }
+
+    // Coverage of every pixel from 0 to 1, as drawn before smoothing.
+    fn coverage(&self, text: &str) -> (u32, u32, Vec<f32>) {
+        let scale = PxScale::from(self.size);
+        let main = self.fonts[0].as_scaled(scale);
+        let baseline = main.ascent();
+        let height = (main.ascent() - main.descent()).ceil().max(1.0) as u32;
+        let (placed, advance) = self.layout(text);
+        let width = advance.ceil().max(1.0) as u32;
+
+        let mut coverage = vec![0.0f32; (width * height) as usize];
+        let mut plot = |x: i64, y: i64, c: f32| {
+            if x >= 0 && y >= 0 && x < width as i64 && y < height as i64 {
+                let pixel = &mut coverage[(y as u32 * width + x as u32) as usize];
+                *pixel = (*pixel + c).min(1.0);
+            }
+        };
+        for glyph in &placed {
+            let font = &self.fonts[glyph.font];
+            let outlined = font.outline_glyph(glyph.id.with_scale_and_position(scale, point(glyph.x, baseline)));
+            if let Some(outlined) = outlined {
+                let bounds = outlined.px_bounds();
+                outlined.draw(|x, y, c| plot(bounds.min.x as i64 + x as i64, bounds.min.y as i64 + y as i64, c));
+            } else if let Some(bitmap) = raster(font, glyph.id, self.size) {
+                // Colour emoji come as bitmaps, their shape is drawn in the foreground colour like any other glyph.
+                let (image, left, top) = bitmap;
+                for (x, y, Luma([alpha])) in image.enumerate_pixels() {
+                    plot((glyph.x + left) as i64 + x as i64, (baseline + top) as i64 + y as i64, *alpha as f32 / 255.0);
+                }
+            }
+        }
+        (width, height, coverage)
+    }
+
+    // Rasterises `text`, 255 where the glyphs are lit. Only antialiased smoothing leaves values in between.
+    pub fn mask(&self, text: &str) -> GrayImage {
+        let (width, height, coverage) = self.coverage(text);
+        GrayImage::from_fn(width, height, |x, y| {
+            let c = coverage[(y * width + x) as usize];
+            let lit = match self.smoothing {
+                Smoothing::Threshold => c >= 0.5,
+                // lit where the coverage beats the Bayer entry for its position
+                Smoothing::Dither => c * 16.0 > BAYER[y as usize % 4][x as usize % 4] as f32 + 0.5,
+                Smoothing::Antialias => return Luma([(c * 255.0).round() as u8]),
+            };
+            Luma([if lit { 255 } else { 0 }])
+        })
+    }
+}
+
+// A bitmap glyph's alpha scaled to the text size, with its offset from the pen position on the baseline.
+fn raster(font: &FontVec, id: GlyphId, size: f32) -> Option<(GrayImage, f32, f32)> {
+    let image = font.glyph_raster_image2(id, u16::MAX)?;
+    if !matches!(image.format, GlyphImageFormat::Png) || image.pixels_per_em == 0 {
+        return None;
+    }
+    let decoded = image::load_from_memory_with_format(image.data, image::ImageFormat::Png).ok()?;
+    let pixels_per_em = font.as_scaled(PxScale::from(size)).h_scale_factor() * font.units_per_em()?;
+    let factor = pixels_per_em / image.pixels_per_em as f32;
+    let (width, height) = ((decoded.width() as f32 * factor).round(), (decoded.height() as f32 * factor).round());
+    if width < 1.0 || height < 1.0 {
+        return None;
+    }
+    let rgba = decoded.to_rgba8();
+    let alpha = GrayImage::from_fn(rgba.width(), rgba.height(), |x, y| Luma([rgba.get_pixel(x, y)[3]]));
+    let scaled = image::imageops::resize(&alpha, width as u32, height as u32, FilterType::Triangle);
+    // The bitmap's offset counts up from the baseline to its bottom edge.
+    let top = -(image.origin.y + image.height as f32) * factor;
+    Some((scaled, image.origin.x * factor, top))
+}
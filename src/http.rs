@@ -0,0 +1,212 @@
+// HTTP frontend to the daemon's device workers, described by docs/openapi.json.
+//
+// GET  /devices                      -> [{"alias": "rex", "connected": true, "queued": 0}]
+// POST /devices/rex/image            multipart "image" and optional "fit" fields -> 202 {"id": 1, "device": "rex", "state": "queued"}
+// PUT  /devices/rex/color            {"rgb": [255, 0, 0]}
+// PUT  /devices/rex/brightness       {"level": 3}
+// PUT  /devices/rex/enable           {"on": false}
+// GET  /uploads/1                    -> {"id": 1, "device": "rex", "state": "done"}
+use crate::{
+    command::Command,
+    config::Panel,
+    daemon::{Daemon, DeviceStatus, Request, Response},
+    error::ErrorKind,
+    fit::{Fit, FitOptions},
+    image::ILedImage,
+    packet::CtnData,
+};
+use axum::{
+    Json, Router,
+    extract::{Multipart, Path, State},
+    http::{StatusCode, header},
+    response::IntoResponse,
+    routing::{get, post, put},
+};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+use tokio::net::TcpListener;
+
+const OPENAPI: &str = include_str!("../docs/openapi.json");
+// Upload statuses kept for polling, the oldest finished ones are dropped past this.
+pub const MAX_UPLOADS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadState {
+    Queued,
+    Done,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadStatus {
+    pub id: u64,
+    pub device: String,
+    pub state: UploadState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+struct Server {
+    daemon: Arc<Daemon>,
+    next_upload: AtomicU64,
+    uploads: Mutex<BTreeMap<u64, UploadStatus>>,
+}
+
+#[derive(Deserialize)]
+struct Color {
+    rgb: [u8; 3],
+}
+
+#[derive(Deserialize)]
+struct Brightness {
+    level: u8,
+}
+
+#[derive(Deserialize)]
+struct Enable {
+    on: bool,
+}
+
+type Reply = (StatusCode, Json<Response>);
+
+fn error(status: StatusCode, e: impl ToString) -> Reply {
+    (status, Json(Response::error(e)))
+}
+
+impl Server {
+    fn known(&self, alias: &str) -> Result<Panel, Reply> {
+        self.daemon
+            .panel(alias)
+            .ok_or_else(|| error(StatusCode::NOT_FOUND, format!("no device with alias {}", alias)))
+    }
+
+    async fn run(&self, alias: String, command: Command) -> Reply {
+        if let Err(reply) = self.known(&alias) {
+            return reply;
+        }
+        let response = self.daemon.handle(Request { device: Some(alias), command }).await;
+        let status = match (response.ok, response.kind) {
+            (true, _) => StatusCode::OK,
+            (false, Some(ErrorKind::Invalid)) => StatusCode::UNPROCESSABLE_ENTITY,
+            (false, Some(ErrorKind::Timeout)) => StatusCode::GATEWAY_TIMEOUT,
+            (false, _) => StatusCode::BAD_GATEWAY,
+        };
+        (status, Json(response))
+    }
+
+    fn remember(&self, status: UploadStatus) {
+        let mut uploads = self.uploads.lock().unwrap();
+        uploads.insert(status.id, status);
+        while uploads.len() > MAX_UPLOADS {
+            let Some(id) = uploads.values().find(|s| s.state != UploadState::Queued).map(|s| s.id) else {
+                break;
+            };
+            uploads.remove(&id);
+        }
+    }
+}
+
+async fn devices(State(server): State<Arc<Server>>) -> Json<Vec<DeviceStatus>> {
+    Json(server.daemon.status())
+}
+
+async fn color(State(server): State<Arc<Server>>, Path(alias): Path<String>, Json(body): Json<Color>) -> Reply {
+    server.run(alias, Command::Color { rgb: body.rgb }).await
+}
+
+async fn brightness(State(server): State<Arc<Server>>, Path(alias): Path<String>, Json(body): Json<Brightness>) -> Reply {
+    server.run(alias, Command::Brightness { level: body.level }).await
+}
+
+async fn enable(State(server): State<Arc<Server>>, Path(alias): Path<String>, Json(body): Json<Enable>) -> Reply {
+    server.run(alias, Command::Enable { on: body.on }).await
+}
+
+// Queues the upload and answers right away, the result is polled from /uploads/{id}.
+async fn image(
+    State(server): State<Arc<Server>>,
+    Path(alias): Path<String>,
+    mut multipart: Multipart,
+) -> Result<(StatusCode, Json<UploadStatus>), Reply> {
+    let panel = server.known(&alias)?;
+    let mut file = None;
+    let mut fit = FitOptions::default();
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| error(StatusCode::BAD_REQUEST, e))?
+    {
+        match field.name() {
+            Some("image") => file = Some(field.bytes().await.map_err(|e| error(StatusCode::BAD_REQUEST, e))?),
+            Some("fit") => {
+                let text = field.text().await.map_err(|e| error(StatusCode::BAD_REQUEST, e))?;
+                fit.fit = Fit::from_str(&text, true).map_err(|e| error(StatusCode::BAD_REQUEST, e))?;
+            }
+            _ => {}
+        }
+    }
+    let file = file.ok_or_else(|| error(StatusCode::BAD_REQUEST, "missing image field"))?;
+    // Decoding and fitting a long animation can take a while, so it stays off the async workers.
+    let image = tokio::task::spawn_blocking(move || ILedImage::from_reader(&file[..], panel, &fit))
+        .await
+        .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .map_err(|e| error(StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    let data = Arc::new(CtnData::new(image.to_bytes()));
+
+    let id = server.next_upload.fetch_add(1, Ordering::SeqCst) + 1;
+    let status = UploadStatus { id, device: alias.clone(), state: UploadState::Queued, error: None };
+    server.remember(status.clone());
+    let worker = server.clone();
+    tokio::spawn(async move {
+        let response = worker.daemon.handle(Request { device: Some(alias), command: Command::Upload(data) }).await;
+        if let Some(status) = worker.uploads.lock().unwrap().get_mut(&id) {
+            status.state = if response.ok { UploadState::Done } else { UploadState::Failed };
+            status.error = response.error;
+        }
+    });
+    Ok((StatusCode::ACCEPTED, Json(status)))
+}
+
+async fn upload(State(server): State<Arc<Server>>, Path(id): Path<u64>) -> Result<Json<UploadStatus>, Reply> {
+    server
+        .uploads
+        .lock()
+        .unwrap()
+        .get(&id)
+        .cloned()
+        .map(Json)
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, format!("no upload with id {}", id)))
+}
+
+async fn openapi() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "application/json")], OPENAPI)
+}
+
+pub fn router(daemon: Arc<Daemon>) -> Router {
+    let server = Arc::new(Server {
+        daemon,
+        next_upload: AtomicU64::new(0),
+        uploads: Mutex::new(BTreeMap::new()),
+    });
+    Router::new()
+        .route("/devices", get(devices))
+        .route("/devices/{alias}/image", post(image))
+        .route("/devices/{alias}/color", put(color))
+        .route("/devices/{alias}/brightness", put(brightness))
+        .route("/devices/{alias}/enable", put(enable))
+        .route("/uploads/{id}", get(upload))
+        .route("/openapi.json", get(openapi))
+        .with_state(server)
+}
+
+pub async fn serve(daemon: Arc<Daemon>, listener: TcpListener) -> std::io::Result<()> {
+    axum::serve(listener, router(daemon)).await
+}
@@ -1,52 +1,222 @@
+use crate::{
+    animation,
+    color::Color,
+    config::Panel,
+    fit::{self, FitOptions},
+};
+use clap::ValueEnum;
 use image::{
-    ImageError, ImageFormat, ImageReader,
-    error::{ImageFormatHint, UnsupportedError, UnsupportedErrorKind},
+    AnimationDecoder, Delay, Frame, ImageError, ImageFormat, ImageReader, RgbImage,
+    codecs::gif::GifDecoder,
+    error::{DecodingError, ImageFormatHint, UnsupportedError, UnsupportedErrorKind},
 };
 use std::{
-    fs::File,
-    io::{Cursor, Read},
+    fmt,
+    fs::{self, File},
+    io::{self, Cursor, Read},
+    path::Path,
+    time::Duration,
 };
 
-pub const IMAGE_METADATA_RGB_COLOR: ImageMetadata = ImageMetadata {
-    unknown1: 0x0000,
-    unknown2: 0x0000,
-    width: 0x0030,
-    height: 0x000c,
-    unknown3: 0x0000,
-    unknown4: 0x0001,
-    unknown5: 0x0001,
-    unknown6: 0x0001,
-    unknown7: 0x0032,
-    unknown8: 0x0064,
-    unknown9: 0x0000,
-};
+// How long each frame of a directory of frames is shown, unless told otherwise.
+pub const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
 
-pub const IMAGE_METADATA_GIF: ImageMetadata = ImageMetadata {
-    unknown1: 0x0000,
-    unknown2: 0x0000,
-    width: 0x0030,
-    height: 0x000c,
-    unknown3: 0x0000,
-    unknown4: 0x0006,
-    unknown5: 0x0001,
-    unknown6: 0x0064,
-    unknown7: 0x0400,
-    unknown8: 0x0064,
-    unknown9: 0x0000,
-};
+// What the data after the header holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode {
+    // raw RGB bytes, row by row
+    Still,
+    // a GIF file
+    Animation,
+    // a code no capture has shown, kept so that headers from captures can still be read
+    Other(u16),
+}
+
+impl Mode {
+    pub const fn code(self) -> u16 {
+        match self {
+            Mode::Still => 0x0001,
+            Mode::Animation => 0x0006,
+            Mode::Other(code) => code,
+        }
+    }
+
+    pub fn from_code(code: u16) -> Self {
+        [Mode::Still, Mode::Animation].into_iter().find(|mode| mode.code() == code).unwrap_or(Mode::Other(code))
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::Still => write!(f, "still"),
+            Mode::Animation => write!(f, "animation"),
+            Mode::Other(code) => write!(f, "mode 0x{:04x}", code),
+        }
+    }
+}
+
+// How the device brings the image on screen. Only Static has been seen in a capture, the other codes follow the
+// order of the official app's effect list and are unconfirmed.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Effect {
+    #[default]
+    Static,
+    ScrollLeft,
+    ScrollRight,
+    ScrollUp,
+    ScrollDown,
+    Blink,
+    Breathe,
+    // a code read back from a header that isn't in the list above
+    #[value(skip)]
+    Other(u16),
+}
+
+impl Effect {
+    pub const fn code(self) -> u16 {
+        match self {
+            Effect::Static => 0x0001,
+            Effect::ScrollLeft => 0x0002,
+            Effect::ScrollRight => 0x0003,
+            Effect::ScrollUp => 0x0004,
+            Effect::ScrollDown => 0x0005,
+            Effect::Blink => 0x0006,
+            Effect::Breathe => 0x0007,
+            Effect::Other(code) => code,
+        }
+    }
+
+    pub fn from_code(code: u16) -> Self {
+        Effect::value_variants().iter().copied().find(|effect| effect.code() == code).unwrap_or(Effect::Other(code))
+    }
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.to_possible_value() {
+            Some(value) => write!(f, "{}", value.get_name()),
+            None => write!(f, "0x{:04x}", self.code()),
+        }
+    }
+}
 
+// The 22 byte header in front of the image data, eleven big-endian u16 fields. Only two headers have ever been
+// captured, one for a still image and one for a GIF (see docs/ouppy.md), and they only pin down three fields:
+// width and height match the panel and mode follows the kind of data. Every other field is kept as its raw value
+// until experiments on a device show what it does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ImageMetadata {
-    unknown1: u16,
-    unknown2: u16,
-    width: u16,
-    height: u16,
-    unknown3: u16,
-    unknown4: u16,
-    unknown5: u16,
-    unknown6: u16,
-    unknown7: u16,
-    unknown8: u16,
-    unknown9: u16,
+    fields: [u16; Self::FIELDS],
+}
+
+impl ImageMetadata {
+    pub const LEN: usize = 22;
+    pub const FIELDS: usize = Self::LEN / 2;
+    const WIDTH: usize = 2;
+    const HEIGHT: usize = 3;
+    const MODE: usize = 5;
+    // The fields the effect, its speed and a playlist's hold time are guessed to live in, see docs/ouppy.md.
+    const EFFECT: usize = 6;
+    const HOLD: usize = 8;
+    const SPEED: usize = 9;
+
+    // The header the official app sends for `mode`, sized for the 48x12 panel.
+    pub const fn new(mode: Mode) -> Self {
+        let fields = match mode {
+            Mode::Still => [0, 0, 48, 12, 0, 0x0001, 0x0001, 0x0001, 0x0032, 0x0064, 0],
+            Mode::Animation => [0, 0, 48, 12, 0, 0x0006, 0x0001, 0x0064, 0x0400, 0x0064, 0],
+            // nothing to copy, only the mode and size are filled in
+            Mode::Other(code) => [0, 0, 48, 12, 0, code, 0, 0, 0, 0, 0],
+        };
+        ImageMetadata { fields }
+    }
+
+    pub const fn size(mut self, width: u16, height: u16) -> Self {
+        self.fields[Self::WIDTH] = width;
+        self.fields[Self::HEIGHT] = height;
+        self
+    }
+
+    pub const fn effect(mut self, effect: Effect) -> Self {
+        self.fields[Self::EFFECT] = effect.code();
+        self
+    }
+
+    pub const fn speed(mut self, speed: u16) -> Self {
+        self.fields[Self::SPEED] = speed;
+        self
+    }
+
+    // How long a playlist shows the image, in the device's unit.
+    pub const fn hold(mut self, hold: u16) -> Self {
+        self.fields[Self::HOLD] = hold;
+        self
+    }
+
+    // The raw value of a field, for the ones nobody has identified yet. None past the end of the header.
+    pub fn field(&self, index: usize) -> Option<u16> {
+        self.fields.get(index).copied()
+    }
+
+    pub fn width(&self) -> u16 {
+        self.fields[Self::WIDTH]
+    }
+
+    pub fn height(&self) -> u16 {
+        self.fields[Self::HEIGHT]
+    }
+
+    // Whether the header is one of the captured ones apart from its size, anything else is untested on a device.
+    pub fn is_captured(&self) -> bool {
+        let mode = self.mode();
+        !matches!(mode, Mode::Other(_)) && *self == ImageMetadata::new(mode).size(self.width(), self.height())
+    }
+
+    pub fn mode(&self) -> Mode {
+        Mode::from_code(self.fields[Self::MODE])
+    }
+
+    pub fn current_effect(&self) -> Effect {
+        Effect::from_code(self.fields[Self::EFFECT])
+    }
+
+    // Reads a header back as it is, whatever its codes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ImageError> {
+        let bytes = bytes
+            .get(..Self::LEN)
+            .ok_or_else(|| decoding_error(format!("{} bytes is too short for an image header", bytes.len())))?;
+        let mut fields = [0; Self::FIELDS];
+        for (field, chunk) in fields.iter_mut().zip(bytes.chunks_exact(2)) {
+            *field = u16::from_be_bytes([chunk[0], chunk[1]]);
+        }
+        Ok(ImageMetadata { fields })
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0; Self::LEN];
+        for (chunk, field) in bytes.chunks_exact_mut(2).zip(self.fields) {
+            chunk.copy_from_slice(&field.to_be_bytes());
+        }
+        bytes
+    }
+}
+
+// The unidentified fields are listed by index, so headers from captures can be told apart at a glance.
+impl fmt::Display for ImageMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}x{}, effect {}, fields", self.mode(), self.width(), self.height(), self.current_effect())?;
+        for (index, field) in self.fields.iter().enumerate() {
+            if ![Self::WIDTH, Self::HEIGHT, Self::MODE, Self::EFFECT].contains(&index) {
+                write!(f, " {}:{:04x}", index, field)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn decoding_error(message: String) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormatHint::Unknown, message))
 }
 
 fn unsupported_error(format: Option<ImageFormat>) -> ImageError {
@@ -61,77 +231,190 @@ fn unsupported_error(format: Option<ImageFormat>) -> ImageError {
     ))
 }
 
+#[derive(Clone)]
 pub struct ILedImage {
     metadata: ImageMetadata,
     data: Vec<u8>,
 }
 
 impl ILedImage {
-    pub fn new(width: u16, height: u16, image_type: ImageMetadata, data: Vec<u8>) -> Self {
-        let mut metadata = image_type;
-        metadata.width = width;
-        metadata.height = height;
+    pub fn new(metadata: ImageMetadata, data: Vec<u8>) -> Self {
         ILedImage { metadata, data }
     }
 
+    pub fn metadata(&self) -> &ImageMetadata {
+        &self.metadata
+    }
+
+    // The pixels or GIF after the header.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    // Plays the image with `effect`, at `speed` when given or else the speed the official app uses for the mode.
+    pub fn with_effect(mut self, effect: Effect, speed: Option<u16>) -> Self {
+        self.metadata = self.metadata.effect(effect);
+        if let Some(speed) = speed {
+            self.metadata = self.metadata.speed(speed);
+        }
+        self
+    }
+
+    pub fn with_hold(mut self, hold: u16) -> Self {
+        self.metadata = self.metadata.hold(hold);
+        self
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
-        bytes.extend(&self.metadata.unknown1.to_be_bytes());
-        bytes.extend(&self.metadata.unknown2.to_be_bytes());
-        bytes.extend(&self.metadata.width.to_be_bytes());
-        bytes.extend(&self.metadata.height.to_be_bytes());
-        bytes.extend(&self.metadata.unknown3.to_be_bytes());
-        bytes.extend(&self.metadata.unknown4.to_be_bytes());
-        bytes.extend(&self.metadata.unknown5.to_be_bytes());
-        bytes.extend(&self.metadata.unknown6.to_be_bytes());
-        bytes.extend(&self.metadata.unknown7.to_be_bytes());
-        bytes.extend(&self.metadata.unknown8.to_be_bytes());
-        bytes.extend(&self.metadata.unknown9.to_be_bytes());
+        let mut bytes = self.metadata.to_bytes().to_vec();
         bytes.extend(&self.data);
         bytes
     }
+
+    // Reads back what `to_bytes` wrote.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ImageError> {
+        let (image, len) = Self::read(bytes)?;
+        if len != bytes.len() {
+            return Err(decoding_error(format!("{} bytes left over after the image", bytes.len() - len)));
+        }
+        Ok(image)
+    }
+
+    // Reads the image at the start of `bytes`, returning it with the number of bytes it took up. There's no telling
+    // where the data of an unknown mode ends, so it takes up the rest.
+    pub fn read(bytes: &[u8]) -> Result<(Self, usize), ImageError> {
+        let metadata = ImageMetadata::from_bytes(bytes)?;
+        let data = &bytes[ImageMetadata::LEN..];
+        let len = match metadata.mode() {
+            Mode::Still => metadata.width() as usize * metadata.height() as usize * 3,
+            Mode::Animation => animation::gif_len(data).ok_or_else(|| decoding_error("truncated GIF".to_string()))?,
+            Mode::Other(_) => data.len(),
+        };
+        let data = data.get(..len).ok_or_else(|| {
+            decoding_error(format!("{} bytes of pixels for a {}x{} image", data.len(), metadata.width(), metadata.height()))
+        })?;
+        Ok((ILedImage::new(metadata, data.to_vec()), ImageMetadata::LEN + len))
+    }
+
+    // What the device shows, each frame with how long it stays up. Still images are a single frame.
+    pub fn frames(&self) -> Result<Vec<(RgbImage, Duration)>, ImageError> {
+        let (width, height) = (self.metadata.width() as u32, self.metadata.height() as u32);
+        match self.metadata.mode() {
+            Mode::Still => {
+                let image = RgbImage::from_raw(width, height, self.data.clone())
+                    .ok_or_else(|| decoding_error("image data doesn't match its size".to_string()))?;
+                Ok(vec![(image, Duration::ZERO)])
+            }
+            Mode::Animation => {
+                let frames = GifDecoder::new(Cursor::new(&self.data))?.into_frames().collect_frames()?;
+                Ok(frames
+                    .iter()
+                    .map(|frame| {
+                        let (numer, denom) = frame.delay().numer_denom_ms();
+                        let delay = Duration::from_micros(numer as u64 * 1000 / denom.max(1) as u64);
+                        (fit::flatten(frame.buffer(), Color::new(0, 0, 0)), delay)
+                    })
+                    .collect())
+            }
+            Mode::Other(_) => Err(decoding_error(format!("can't draw {}", self.metadata.mode()))),
+        }
+    }
 }
 
 impl ILedImage {
-    pub fn solid_color(width: u16, height: u16, r: u8, g: u8, b: u8) -> Self {
-        let mut data = Vec::with_capacity((width as usize) * (height as usize) * 3);
-        for _ in 0..(width as usize) * (height as usize) {
-            data.push(r);
-            data.push(g);
-            data.push(b);
+    // The whole panel in `color`, corrected and quantised like any other image.
+    pub fn solid_color(panel: Panel, color: Color, fit: &FitOptions) -> Self {
+        let image = RgbImage::from_pixel(panel.width as u32, panel.height as u32, image::Rgb(color.rgb()));
+        ILedImage::from_rgb(&fit::fit(&image, panel, fit))
+    }
+
+    pub fn from_rgb(image: &RgbImage) -> Self {
+        let (width, height) = (image.width() as u16, image.height() as u16);
+        ILedImage::new(ImageMetadata::new(Mode::Still).size(width, height), image.as_raw().clone())
+    }
+
+    // Fits every frame to the panel and encodes them as a looping GIF, the format the device plays animations from.
+    pub fn from_frames(frames: Vec<Frame>, panel: Panel, fit: &FitOptions) -> Result<Self, image::ImageError> {
+        let frames: Vec<_> = frames
+            .iter()
+            .map(|frame| {
+                let image = fit::fit(&fit::flatten(frame.buffer(), fit.letterbox), panel, fit);
+                (image, animation::delay_cs(frame.delay()))
+            })
+            .collect();
+        let data = animation::encode(&frames, panel)?;
+        Ok(ILedImage::new(ImageMetadata::new(Mode::Animation).size(panel.width, panel.height), data))
+    }
+
+    pub fn from_file(file_path: File, panel: Panel, fit: &FitOptions) -> Result<Self, image::ImageError> {
+        Self::from_reader(std::io::BufReader::new(file_path), panel, fit)
+    }
+
+    // An image file, or a directory of numbered frames shown `DEFAULT_FRAME_DELAY` apart.
+    pub fn from_path(path: &Path, panel: Panel, fit: &FitOptions) -> Result<Self, image::ImageError> {
+        if path.is_dir() {
+            return Self::from_dir(path, DEFAULT_FRAME_DELAY, panel, fit);
         }
-        ILedImage::new(width, height, IMAGE_METADATA_RGB_COLOR, data)
+        Self::from_file(File::open(path)?, panel, fit)
     }
 
-    pub fn from_file(file_path: File) -> Result<Self, image::ImageError> {
-        let mut buf_reader = std::io::BufReader::new(file_path);
+    // An animation from the images in `dir`, ordered by the number in their names so that frame9.png comes
+    // before frame10.png. Files that aren't images are skipped.
+    pub fn from_dir(dir: &Path, delay: Duration, panel: Panel, fit: &FitOptions) -> Result<Self, image::ImageError> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() && ImageFormat::from_path(&path).is_ok() {
+                paths.push(path);
+            }
+        }
+        if paths.is_empty() {
+            let message = format!("{}: no images in directory", dir.display());
+            return Err(io::Error::new(io::ErrorKind::NotFound, message).into());
+        }
+        paths.sort_by_cached_key(|path| (frame_number(path), path.clone()));
+        let frames = paths
+            .iter()
+            .map(|path| {
+                let image = image::open(path)?.into_rgba8();
+                Ok(Frame::from_parts(image, 0, 0, Delay::from_saturating_duration(delay)))
+            })
+            .collect::<Result<Vec<_>, ImageError>>()?;
+        Self::from_frames(frames, panel, fit)
+    }
+
+    // Images are fitted to `panel`. GIFs already made for it are sent as they are unless colours need correcting,
+    // other animations are decoded and re-encoded as GIFs.
+    pub fn from_reader(mut buf_reader: impl Read, panel: Panel, fit: &FitOptions) -> Result<Self, image::ImageError> {
         let mut data = Vec::new();
-        buf_reader
-            .read_to_end(&mut data)
-            .expect("Unable to read file data");
-        let mut image_reader = ImageReader::new(Cursor::new(data.clone())).with_guessed_format()?;
+        buf_reader.read_to_end(&mut data).map_err(image::ImageError::IoError)?;
+        let image_reader = ImageReader::new(Cursor::new(&data)).with_guessed_format()?;
         let format = image_reader.format().ok_or(unsupported_error(None))?;
-
-        let metadata = match format {
-            ImageFormat::Gif => IMAGE_METADATA_GIF,
-            ImageFormat::Png
+        match format {
+            ImageFormat::Gif
+            | ImageFormat::Png
             | ImageFormat::Jpeg
             | ImageFormat::Bmp
             | ImageFormat::Tiff
-            | ImageFormat::WebP => IMAGE_METADATA_RGB_COLOR,
+            | ImageFormat::WebP => {}
             _ => Err(unsupported_error(Some(format)))?,
         };
-        image_reader.set_format(format);
-        let image = image_reader.decode()?;
-        let width = image.width() as u16;
-        let height = image.height() as u16;
-
-        let iledimg = if format == ImageFormat::Gif {
-            ILedImage::new(width, height, metadata, data)
-        } else {
-            ILedImage::new(width, height, metadata, image.to_rgb8().into_raw())
-        };
 
-        Ok(iledimg)
+        if format == ImageFormat::Gif && !fit.changes_colours() && animation::conforms(&data, panel) {
+            return Ok(ILedImage::new(ImageMetadata::new(Mode::Animation).size(panel.width, panel.height), data));
+        }
+        if let Some(frames) = animation::frames(&data, format)? {
+            return ILedImage::from_frames(frames, panel, fit);
+        }
+        let image = image_reader.decode()?;
+        Ok(ILedImage::from_rgb(&fit::fit(&fit::flatten(&image.to_rgba8(), fit.letterbox), panel, fit)))
     }
 }
+
+// The last run of digits in a file name, frames without one go first.
+fn frame_number(path: &Path) -> Option<u64> {
+    let stem = path.file_stem()?.to_str()?;
+    let end = stem.rfind(|c: char| c.is_ascii_digit())? + 1;
+    let start = stem[..end].rfind(|c: char| !c.is_ascii_digit()).map_or(0, |i| i + 1);
+    stem[start..end].parse().ok()
+}
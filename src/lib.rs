@@ -0,0 +1,26 @@
+pub mod animation;
+pub mod ble;
+#[cfg(target_os = "linux")]
+pub mod bluez;
+pub mod color;
+pub mod command;
+pub mod config;
+pub mod correction;
+pub mod daemon;
+pub mod error;
+pub mod fit;
+pub mod fleet;
+pub mod font;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod image;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod packet;
+pub mod playlist;
+pub mod preview;
+pub mod profile;
+pub mod quantise;
+pub mod send;
+pub mod sim;
+pub mod text;
@@ -1,88 +1,976 @@
-use crate::image::ILedImage;
-use clap::{ArgGroup, Parser};
-use std::{error::Error, fs::File, path::PathBuf};
-
-mod ble;
-mod image;
-mod packet;
-mod send;
-
-#[derive(clap::ValueEnum, Clone, Debug)]
-enum ColorArg {
-    Red,
-    Green,
-    Blue,
-    Yellow,
-    Cyan,
-    Magenta,
-    White,
-    Black,
-}
-
-impl ColorArg {
-    fn to_rgb(&self) -> (u8, u8, u8) {
-        match self {
-            ColorArg::Red => (255, 0, 0),
-            ColorArg::Green => (0, 255, 0),
-            ColorArg::Blue => (0, 0, 255),
-            ColorArg::Yellow => (255, 255, 0),
-            ColorArg::Cyan => (0, 255, 255),
-            ColorArg::Magenta => (255, 0, 255),
-            ColorArg::White => (255, 255, 255),
-            ColorArg::Black => (0, 0, 0),
-        }
-    }
-}
-// -s newpass [old pass] | [-b brightness] [-e enable] [-p password] [-i image | -c color] 
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use iledcolor_rs::{
+    ble::{self, Adapter, AdapterSelector, ConnectOptions, Device, DeviceMatcher},
+    color::Color,
+    command::{self, Command},
+    config::{DeviceEntry, Inventory},
+    correction::Correction,
+    daemon::Response,
+    fit::{Filter, Fit, FitOptions},
+    fleet::{self, FleetOptions, Job, Report},
+    font::{OutlineFont, Smoothing},
+    image::{Effect, ILedImage, Mode},
+    packet::{CtnData, Password},
+    playlist::{self, Playlist},
+    preview,
+    profile::{self, PanelProfile},
+    quantise::{Dither, Levels, Quantise},
+    send::Session,
+    text::{self, Align, Direction, MarqueeOptions, TextStyle, Typeface},
+};
+use image::RgbImage;
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    error::Error,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
 #[derive(Parser, Debug)]
-#[command(
-    version,
-    about,
-    group(ArgGroup::new("input").args(["image_path", "color"]).required(true))
-)]
+#[command(version, about)]
 pub struct Cli {
-    #[arg(short, long)]
-    pub device_name: String,
-    #[arg(short, long)]
-    pub image_path: Option<PathBuf>,
-    #[arg(short, long)]
-    color: Option<ColorArg>,
+    #[command(flatten)]
+    global: Global,
+    #[command(subcommand)]
+    command: Cmd,
+}
+
+#[derive(Args, Debug)]
+struct Global {
+    /// Device name, id or config alias, repeat to target several devices
+    #[arg(short, long, global = true)]
+    device: Vec<String>,
+    /// Every device of a config file group, repeatable
+    #[arg(short, long, global = true, requires = "config")]
+    group: Vec<String>,
+    /// Every device whose name starts with this, found until the scan deadline
+    #[arg(long, global = true, conflicts_with_all = ["device", "group"])]
+    prefix: Option<String>,
+    /// TOML or YAML (.yaml, .yml) inventory of device entries: alias, name or id, password, brightness, panel and groups
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Six digit password, for devices not listed in the config file
+    #[arg(short, long, global = true)]
+    password: Option<Password>,
+    /// Panel profile (collar) or size as WIDTHxHEIGHT, for devices not listed in the config file.
+    /// Detected from the name a device advertises when not given, `info` also checks the handshake. Other commands
+    /// encode their data before connecting, so they go by the name alone and log a warning if the handshake disagrees
+    #[arg(long, global = true)]
+    panel: Option<PanelProfile>,
+    /// Bluetooth adapter to use, by index or name/address as listed by `adapters`
+    #[arg(short, long, global = true)]
+    adapter: Option<AdapterSelector>,
+    /// Seconds to scan for devices before giving up, 0 scans forever
+    #[arg(short, long, global = true, default_value_t = 30)]
+    timeout: u64,
+    /// Connection attempts retried before giving up, also used when the device drops mid-upload
+    #[arg(long, global = true, default_value_t = 3)]
+    retries: u32,
+    /// Devices worked on at the same time
+    #[arg(long, global = true, default_value_t = 4)]
+    parallel: usize,
+    /// Whole attempts repeated per device after a failure
+    #[arg(long, global = true, default_value_t = 1)]
+    upload_retries: u32,
+    /// Print results as a single line of JSON
+    #[arg(long, global = true)]
+    json: bool,
+    /// Show what will be uploaded in the terminal first, animations played once
+    #[arg(long, global = true)]
+    preview: bool,
+    /// Stop after the preview without uploading
+    #[arg(long, global = true, requires = "preview")]
+    no_send: bool,
+    /// Send image header values that have never been seen in a capture of the official app, such as effects
+    /// other than static and --speed. Devices may ignore them or misbehave
+    #[arg(long, global = true)]
+    experimental: bool,
+    /// Log more, repeat for more detail
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+}
+
+#[derive(Subcommand, Debug)]
+enum Cmd {
+    #[command(flatten)]
+    Draw(DrawCmd),
+    /// Set the brightness, 0 is the brightest and 10 the dimmest
+    Brightness {
+        #[arg(value_parser = clap::value_parser!(u8).range(0..=10))]
+        level: u8,
+    },
+    /// Turn the panel on or off
+    Power { state: PowerState },
+    /// Set, change or remove the device password
+    Password {
+        #[command(subcommand)]
+        op: PasswordCmd,
+    },
+    /// Connect and show what the device reports
+    Info,
+    /// List the devices seen until the timeout, narrowed down by --prefix
+    Scan,
+    /// Change the name a single device advertises
+    Rename { name: String },
+    /// List a single device's GATT services and characteristics
+    Discover,
+    /// List the bluetooth adapters and their availability
+    Adapters,
+    /// Write what a send, color, text, marquee or playlist command would upload to a file instead, no bluetooth needed
+    ///
+    /// Still images are written pixel for pixel in the format --out's extension names. A single animation written to
+    /// a .gif is the GIF the device receives, anything else with several frames is written one frame under the other.
+    Render {
+        #[arg(long)]
+        out: PathBuf,
+        #[command(subcommand)]
+        command: DrawCmd,
+    },
+    /// Decode a saved or captured upload, print its headers and write its frames to a PNG, one under the other
+    ///
+    /// Takes the reassembled upload data, checksum first, or a bare image, as binary or a hex dump.
+    Inspect {
+        path: PathBuf,
+        /// PNG to write, the input's name with a .png extension by default
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Read commands from stdin and run them on a single connection
+    ///
+    /// One command per line: color <name>, image <path>, brightness <0-10>, on, off,
+    /// password set|change|unset <digits>.., each answered with "ok" or "error: ..."
+    // --stdin was the original spelling, kept working for existing scripts
+    #[command(long_flag_alias = "stdin")]
+    Stdin,
+}
+
+// Commands that draw something to upload, what `render` writes to a file instead.
+#[derive(Subcommand, Debug)]
+enum DrawCmd {
+    /// Upload an image, a GIF, animated PNG or WebP, or a directory of numbered frames, fitted to the panel
+    Send {
+        path: PathBuf,
+        #[arg(long, value_enum, default_value_t = Fit::Contain)]
+        fit: Fit,
+        /// Resampling filter used when scaling, nearest keeps pixel art sharp
+        #[arg(long, value_enum, default_value_t = Filter::Lanczos3)]
+        filter: Filter,
+        /// Colour of the borders --fit contain and center leave, and behind transparent pixels
+        #[arg(long, default_value = "black")]
+        letterbox: Color,
+        #[command(flatten)]
+        correction: CorrectionArgs,
+        #[command(flatten)]
+        quantise: QuantiseArgs,
+        /// Milliseconds each frame is shown when sending a directory of frames
+        #[arg(long, default_value_t = 100)]
+        frame_delay: u64,
+        #[command(flatten)]
+        effect: EffectArgs,
+    },
+    /// Fill the panel with one colour: #RRGGBB, #RGB, rgb(r, g, b), hsv(h, s%, v%) or a CSS colour name
+    Color {
+        color: Color,
+        #[command(flatten)]
+        correction: CorrectionArgs,
+        #[command(flatten)]
+        quantise: QuantiseArgs,
+    },
+    /// Write text in the built-in 5x7 pixel font or a --font, cut off where it doesn't fit the panel
+    Text {
+        #[command(flatten)]
+        text: TextArgs,
+        #[arg(long, value_enum, default_value_t = Align::Center)]
+        align: Align,
+        #[command(flatten)]
+        correction: CorrectionArgs,
+        #[command(flatten)]
+        quantise: QuantiseArgs,
+        #[command(flatten)]
+        effect: EffectArgs,
+    },
+    /// Scroll text across the panel in the built-in 5x7 pixel font or a --font
+    Marquee {
+        #[command(flatten)]
+        text: TextArgs,
+        /// Pixels per second
+        #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u32).range(1..=500))]
+        speed: u32,
+        /// Way the text moves
+        #[arg(long, value_enum, default_value_t = Direction::Left)]
+        direction: Direction,
+        /// Blank pixels before the text comes round again, defaults to the panel width
+        #[arg(long)]
+        gap: Option<u32>,
+        #[command(flatten)]
+        correction: CorrectionArgs,
+        #[command(flatten)]
+        quantise: QuantiseArgs,
+    },
+    /// Experimental: upload several images or animations for the device to cycle through. The layout is guessed, so
+    /// it's only sent with --experimental
+    Playlist {
+        /// Image, GIF or directory of frames, optionally with the seconds it shows for: logo.png@10. An @ not followed
+        /// by seconds stays part of the path
+        #[arg(required = true, num_args = 1..=playlist::MAX_PROGRAMS, value_parser = playlist_item)]
+        items: Vec<(PathBuf, Option<f64>)>,
+        /// Seconds each item without its own is shown
+        #[arg(long, default_value_t = 5.0, value_parser = seconds_arg)]
+        duration: f64,
+        #[arg(long, value_enum, default_value_t = Fit::Contain)]
+        fit: Fit,
+        #[command(flatten)]
+        correction: CorrectionArgs,
+        #[command(flatten)]
+        quantise: QuantiseArgs,
+    },
+}
+
+#[derive(Args, Debug)]
+struct CorrectionArgs {
+    /// Gamma applied before sending, around 2.2 stops photos looking washed out on the LEDs
+    #[arg(long, default_value_t = 1.0, value_parser = positive_arg)]
+    gamma: f32,
+    /// Red, green and blue gains for white balance, e.g. 1,0.85,0.7
+    #[arg(long, default_value = "1,1,1", value_parser = gains_arg)]
+    white_balance: [f32; 3],
+    /// Dims frames whose channels add up to more than this fraction of an all-white panel, e.g. 0.4
+    #[arg(long, value_parser = fraction_arg)]
+    max_power: Option<f32>,
+}
+
+impl CorrectionArgs {
+    fn correction(&self) -> Correction {
+        Correction { gamma: self.gamma, gain: self.white_balance, max_power: self.max_power }
+    }
+}
+
+fn positive_arg(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(value) if value > 0.0 && value.is_finite() => Ok(value),
+        _ => Err(format!("{} is not a positive number", s)),
+    }
+}
+
+fn fraction_arg(s: &str) -> Result<f32, String> {
+    positive_arg(s).and_then(|value| match value {
+        ..=1.0 => Ok(value),
+        _ => Err(format!("{} is more than 1", s)),
+    })
+}
+
+fn gains_arg(s: &str) -> Result<[f32; 3], String> {
+    let gains = s
+        .split(',')
+        .map(|gain| match gain.trim().parse::<f32>() {
+            Ok(value) if value >= 0.0 && value.is_finite() => Ok(value),
+            _ => Err(format!("{} is not a gain", gain)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    gains.try_into().map_err(|_| "white balance takes three gains: red,green,blue".to_string())
+}
+
+#[derive(Args, Debug)]
+struct QuantiseArgs {
+    /// Bits per colour channel, fewer colours for panels or modes that can't show all of them
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=8))]
+    bits: Option<u8>,
+    /// Limit the image to these colours, repeat for each one
+    #[arg(long, conflicts_with = "bits")]
+    palette: Vec<Color>,
+    /// How colours between the --bits levels or --palette colours are approximated
+    #[arg(long, value_enum, default_value_t = Dither::FloydSteinberg)]
+    dither: Dither,
+}
+
+impl QuantiseArgs {
+    fn quantise(&self) -> Quantise {
+        let levels = match self.bits {
+            Some(bits) => Levels::Bits(bits),
+            None if !self.palette.is_empty() => Levels::Palette(self.palette.clone()),
+            None => Levels::Full,
+        };
+        Quantise { levels, dither: self.dither }
+    }
+}
+
+// For images drawn at the panel's size, where only the colours change.
+fn colour_options(correction: &CorrectionArgs, quantise: &QuantiseArgs) -> FitOptions {
+    FitOptions { correction: correction.correction(), quantise: quantise.quantise(), ..FitOptions::default() }
+}
+
+#[derive(Args, Debug)]
+struct EffectArgs {
+    /// Experimental: how the device brings the image on screen. Only static has been seen in a capture, the others
+    /// are guessed codes and need --experimental to be sent
+    #[arg(long, value_enum, default_value_t = Effect::Static)]
+    effect: Effect,
+    /// Experimental: effect speed, in the header field where the official app always has 100. Needs --experimental to
+    /// be sent
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..=100))]
+    speed: Option<u16>,
+}
+
+impl EffectArgs {
+    fn apply(&self, image: ILedImage) -> ILedImage {
+        image.with_effect(self.effect, self.speed)
+    }
+}
+
+#[derive(Args, Debug)]
+struct TextArgs {
+    text: String,
+    /// Colour of the letters, in any form `color` accepts
+    #[arg(long, default_value = "white")]
+    fg: Color,
+    /// Colour behind the letters
+    #[arg(long, default_value = "black")]
+    bg: Color,
+    /// TrueType/OpenType font instead of the pixel font, repeat to add fallbacks for characters it lacks
+    #[arg(long)]
+    font: Vec<PathBuf>,
+    /// Font size in pixels, ascenders to descenders
+    #[arg(long, default_value_t = 12.0, requires = "font")]
+    size: f32,
+    /// How anti-aliased glyph edges become lit or unlit pixels
+    #[arg(long, value_enum, default_value_t = Smoothing::Threshold, requires = "font")]
+    smoothing: Smoothing,
+}
+
+impl TextArgs {
+    fn style(&self, align: Align) -> TextStyle {
+        TextStyle { foreground: self.fg, background: self.bg, align }
+    }
+
+    fn typeface(&self) -> std::io::Result<Typeface> {
+        if self.font.is_empty() {
+            return Ok(Typeface::Pixel);
+        }
+        Ok(Typeface::Outline(OutlineFont::load(&self.font, self.size, self.smoothing)?))
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum PowerState {
+    On,
+    Off,
+}
+
+#[derive(Subcommand, Debug)]
+enum PasswordCmd {
+    /// Protect a device that has no password yet
+    Set {
+        #[arg(value_parser = password_arg)]
+        new: String,
+    },
+    Change {
+        #[arg(value_parser = password_arg)]
+        old: String,
+        #[arg(value_parser = password_arg)]
+        new: String,
+    },
+    Unset {
+        #[arg(value_parser = password_arg)]
+        old: String,
+    },
+}
+
+fn password_arg(s: &str) -> Result<String, String> {
+    s.parse::<Password>().map(|_| s.to_string())
+}
+
+fn seconds_arg(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(seconds) if seconds > 0.0 && seconds.is_finite() => Ok(seconds),
+        _ => Err(format!("{} is not a positive number of seconds", s)),
+    }
+}
+
+// path@seconds, or just a path. An @ not followed by seconds is part of the path, as in logo@2x.png.
+fn playlist_item(s: &str) -> Result<(PathBuf, Option<f64>), String> {
+    match s.rsplit_once('@').and_then(|(path, seconds)| Some((path, seconds_arg(seconds).ok()?))) {
+        Some((path, seconds)) => Ok((PathBuf::from(path), Some(seconds))),
+        None => Ok((PathBuf::from(s), None)),
+    }
+}
+
+// Hex dumps as written by capture tools, spaces, newlines and colons allowed, or the bytes as they are.
+fn hex_or_binary(bytes: Vec<u8>) -> Vec<u8> {
+    let digits: Vec<u8> = bytes.iter().copied().filter(|b| !b.is_ascii_whitespace() && *b != b':').collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) || !digits.iter().all(u8::is_ascii_hexdigit) {
+        return bytes;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+        .collect()
+}
+
+// Uploads the official app has never been seen sending only go out with --experimental.
+fn captured(data: &CtnData) -> Result<(), Box<dyn Error>> {
+    if data.programs() != 1 {
+        let message = "uploads of more than one program have never been seen in a capture, pass --experimental to send it anyway";
+        return Err(message.into());
+    }
+    for program in Playlist::from_ctn_data(data)?.programs() {
+        if !program.metadata().is_captured() {
+            let message = format!(
+                "the header ({}) has values never seen in a capture, pass --experimental to send it anyway",
+                program.metadata()
+            );
+            return Err(message.into());
+        }
+    }
+    Ok(())
+}
+
+// Draws an encoded upload the way the device will show it, every program and frame.
+async fn show(data: &CtnData, profile: &PanelProfile) -> Result<(), Box<dyn Error>> {
+    let mut stdout = std::io::stdout();
+    println!("{}:", profile);
+    for program in Playlist::from_ctn_data(data)?.programs() {
+        preview::play(&mut stdout, &program.frames()?).await?;
+    }
+    Ok(())
+}
+
+// Every frame of every program, one under the other.
+fn film_strip(programs: &[ILedImage]) -> Result<RgbImage, Box<dyn Error>> {
+    let mut frames = Vec::new();
+    for program in programs {
+        frames.extend(program.frames()?.into_iter().map(|(frame, _)| frame));
+    }
+    let width = frames.iter().map(|frame| frame.width()).max().unwrap_or(0);
+    let height = frames.iter().map(|frame| frame.height()).sum();
+    let mut strip = RgbImage::new(width, height);
+    let mut y = 0;
+    for frame in &frames {
+        image::imageops::replace(&mut strip, frame, 0, y);
+        y += frame.height() as i64;
+    }
+    Ok(strip)
 }
 
-#[tokio::main]      // TODO rewrite main function as message queue with arg handling; stdin; sockets?
+fn write_render(data: &CtnData, out: &Path) -> Result<(), Box<dyn Error>> {
+    let playlist = Playlist::from_ctn_data(data)?;
+    let gif = out.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("gif"));
+    if let [program] = playlist.programs()
+        && program.metadata().mode() == Mode::Animation
+        && gif
+    {
+        std::fs::write(out, program.data()).map_err(|e| format!("{}: {}", out.display(), e))?;
+    } else {
+        film_strip(playlist.programs())?.save(out).map_err(|e| format!("{}: {}", out.display(), e))?;
+    }
+    Ok(())
+}
+
+fn inspect(path: &Path, out: Option<PathBuf>) -> Result<(), Box<dyn Error>> {
+    let bytes = hex_or_binary(std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?);
+    let programs = match CtnData::from_bytes(&bytes) {
+        Ok(ctn) => {
+            println!("upload: crc32 0x{:08x}, {} programs, {} bytes", ctn.crc32, ctn.programs(), ctn.data.len());
+            Playlist::from_ctn_data(&ctn)?.programs().to_vec()
+        }
+        Err(e) => vec![ILedImage::from_bytes(&bytes).map_err(|_| e)?],
+    };
+    // Headers are printed even when the data can't be drawn, unknown modes included.
+    let mut drawable = Vec::new();
+    for (i, program) in programs.iter().enumerate() {
+        match program.frames() {
+            Ok(frames) => {
+                println!("program {}: {}, {} frames", i + 1, program.metadata(), frames.len());
+                drawable.push(program.clone());
+            }
+            Err(e) => println!("program {}: {}, {} bytes not drawn: {}", i + 1, program.metadata(), program.data().len(), e),
+        }
+    }
+    if drawable.is_empty() {
+        return Ok(());
+    }
+    let preview = film_strip(&drawable)?;
+    let out = out.unwrap_or_else(|| path.with_extension("png"));
+    if out == path {
+        return Err(format!("{} would overwrite the input, pick another --out", out.display()).into());
+    }
+    preview.save(&out).map_err(|e| format!("{}: {}", out.display(), e))?;
+    println!("wrote {}", out.display());
+    Ok(())
+}
+
+// Prints `value` as JSON or `text` line by line.
+fn emit(json: bool, value: &impl Serialize, text: impl IntoIterator<Item = String>) -> Result<(), Box<dyn Error>> {
+    if json {
+        println!("{}", serde_json::to_string(value)?);
+    } else {
+        for line in text {
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+// What the device options pick out: config entries, plus names or ids the config doesn't know.
+struct Targets {
+    entries: Vec<DeviceEntry>,
+    matcher: DeviceMatcher,
+}
+
+impl Targets {
+    fn new(global: &Global, inventory: &Inventory) -> Result<Self, Box<dyn Error>> {
+        let mut entries: Vec<DeviceEntry> = Vec::new();
+        let mut names = Vec::new();
+        for device in &global.device {
+            match inventory.device(device) {
+                Ok(entry) => entries.push(entry.clone()),
+                Err(_) => names.push(device.clone()),
+            }
+        }
+        for group in &global.group {
+            entries.extend(inventory.group(group)?.into_iter().cloned());
+        }
+        let mut seen = HashSet::new();
+        entries.retain(|e| seen.insert(e.alias.clone()));
+
+        let matcher = match &global.prefix {
+            Some(prefix) => DeviceMatcher::Prefix(prefix.clone()),
+            None if entries.is_empty() && names.is_empty() => {
+                return Err("no device given, use --device, --group or --prefix".into());
+            }
+            None => DeviceMatcher::Names(entries.iter().map(|e| e.target().to_string()).chain(names).collect()),
+        };
+        Ok(Targets { entries, matcher })
+    }
+
+    fn entry(&self, name: Option<&str>, id: &str) -> Option<&DeviceEntry> {
+        self.entries.iter().find(|e| e.matches(name, id))
+    }
+}
+
+struct Found {
+    devices: Vec<(Device, Option<String>, Option<DeviceEntry>)>,
+    missing: Vec<String>,
+}
+
+async fn find(adapter: &Adapter, targets: &Targets, options: &ConnectOptions) -> Result<Found, Box<dyn Error>> {
+    let mut devices = Vec::new();
+    let mut seen = Vec::new();
+    for device in adapter.find_all(&targets.matcher, options).await? {
+        let name = device.name().await;
+        let id = device.id();
+        let entry = targets.entry(name.as_deref(), &id).cloned();
+        seen.push((name.clone(), id));
+        devices.push((device, name, entry));
+    }
+    let missing = targets.matcher.missing(&seen);
+    if devices.is_empty() {
+        return Err("no device found".into());
+    }
+    Ok(Found { devices, missing })
+}
+
+struct Context {
+    global: Global,
+    inventory: Inventory,
+    options: ConnectOptions,
+    render: Option<PathBuf>, // where `render` writes the upload instead of sending it
+}
+
+impl Context {
+    fn password(&self, entry: Option<&DeviceEntry>) -> Option<Password> {
+        entry.and_then(|e| e.password()).or(self.global.password)
+    }
+
+    // The config's profile for listed devices, then --panel, then what the advertised name and, once connected,
+    // the handshake reply give away.
+    fn profile(&self, entry: Option<&DeviceEntry>, name: Option<&str>, connect_reply: Option<[u8; 2]>) -> PanelProfile {
+        match entry {
+            Some(entry) => entry.profile(),
+            None => self
+                .global
+                .panel
+                .or_else(|| name.and_then(|name| PanelProfile::detect(name, connect_reply)))
+                .unwrap_or_default(),
+        }
+    }
+
+    // Encodes an image per panel profile up front, so bad input stops before connecting to anything,
+    // then gives every device the one for its profile.
+    async fn upload<E: Into<Box<dyn Error>>>(
+        &self,
+        render: impl Fn(&PanelProfile) -> Result<ILedImage, E>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.upload_data(|profile| render(profile).map(|image| CtnData::new(image.to_bytes())))
+            .await
+    }
+
+    // Like `upload`, for streams that aren't a single image.
+    async fn upload_data<E: Into<Box<dyn Error>>>(
+        &self,
+        render: impl Fn(&PanelProfile) -> Result<CtnData, E>,
+    ) -> Result<(), Box<dyn Error>> {
+        // rendering to a file draws for the --panel profile alone
+        let profiles = match self.render {
+            Some(_) => HashSet::from([self.global.panel.unwrap_or_default()]),
+            None => self.profiles(),
+        };
+        let sending = self.render.is_none() && !self.global.no_send;
+        let mut images = HashMap::new();
+        for profile in profiles {
+            let data = render(&profile).map_err(Into::into)?;
+            profile.check(&data)?;
+            if sending && !self.global.experimental {
+                captured(&data)?;
+            }
+            images.insert(profile, Arc::new(data));
+        }
+        if self.global.preview {
+            let mut profiles: Vec<_> = images.keys().copied().collect();
+            profiles.sort_by_key(|profile| (profile.panel.width, profile.panel.height, profile.name));
+            for profile in profiles {
+                show(&images[&profile], &profile).await?;
+            }
+            if self.global.no_send {
+                return Ok(());
+            }
+        }
+        if let Some(out) = &self.render {
+            return write_render(&images[&self.global.panel.unwrap_or_default()], out);
+        }
+        self.fleet(|entry, profile| with_brightness(vec![Command::Upload(images[profile].clone())], entry))
+            .await
+    }
+
+    // Every profile a device may turn out to have, any built-in one when devices outside the config are detected.
+    fn profiles(&self) -> HashSet<PanelProfile> {
+        let mut profiles: HashSet<PanelProfile> = self.inventory.devices.iter().map(DeviceEntry::profile).collect();
+        match self.global.panel {
+            Some(profile) => profiles.insert(profile),
+            None => {
+                profiles.extend(profile::PROFILES);
+                profiles.insert(PanelProfile::default())
+            }
+        };
+        profiles
+    }
+
+    // Runs the commands `commands` picks for each targeted device and reports per device.
+    async fn fleet(
+        &self,
+        commands: impl Fn(Option<&DeviceEntry>, &PanelProfile) -> Vec<Command>,
+    ) -> Result<(), Box<dyn Error>> {
+        #[derive(Serialize)]
+        struct Output {
+            devices: Vec<Report>,
+            missing: Vec<String>,
+        }
+
+        let targets = Targets::new(&self.global, &self.inventory)?;
+        let adapter = ble::adapter(self.global.adapter.as_ref()).await?;
+        let found = find(&adapter, &targets, &self.options).await?;
+        let jobs = found
+            .devices
+            .into_iter()
+            .map(|(device, name, entry)| {
+                // The commands are built before connecting, so this is the name alone. The session warns if the
+                // handshake then doesn't fit.
+                let profile = self.profile(entry.as_ref(), name.as_deref(), None);
+                Job {
+                    password: self.password(entry.as_ref()),
+                    commands: commands(entry.as_ref(), &profile),
+                    profile,
+                    device,
+                }
+            })
+            .collect();
+        let fleet_options = FleetOptions {
+            parallel: self.global.parallel,
+            retries: self.global.upload_retries,
+        };
+        let devices = fleet::run(&adapter, jobs, &self.options, &fleet_options).await;
+        let failed = devices.iter().filter(|r| r.result.is_err()).count() + found.missing.len();
+        let total = devices.len() + found.missing.len();
+        let text: Vec<String> = devices
+            .iter()
+            .map(|r| r.to_string())
+            .chain(found.missing.iter().map(|name| format!("{}: not found", name)))
+            .collect();
+        emit(self.global.json, &Output { devices, missing: found.missing }, text)?;
+        if failed > 0 {
+            return Err(format!("{} of {} device(s) failed", failed, total).into());
+        }
+        Ok(())
+    }
+
+    async fn single(&self) -> Result<(Adapter, Device, Option<DeviceEntry>), Box<dyn Error>> {
+        let targets = Targets::new(&self.global, &self.inventory)?;
+        if matches!(&targets.matcher, DeviceMatcher::Names(names) if names.len() == 1) {
+            let adapter = ble::adapter(self.global.adapter.as_ref()).await?;
+            let mut found = find(&adapter, &targets, &self.options).await?;
+            let (device, _, entry) = found.devices.remove(0);
+            return Ok((adapter, device, entry));
+        }
+        Err("this command works on a single device, give exactly one --device".into())
+    }
+
+    async fn info(&self) -> Result<(), Box<dyn Error>> {
+        #[derive(Serialize)]
+        struct DeviceInfo {
+            alias: Option<String>,
+            name: Option<String>,
+            id: String,
+            profile: String,
+            password: Option<bool>,
+            error: Option<String>,
+        }
+
+        let targets = Targets::new(&self.global, &self.inventory)?;
+        let adapter = ble::adapter(self.global.adapter.as_ref()).await?;
+        let found = find(&adapter, &targets, &self.options).await?;
+        let mut infos = Vec::new();
+        for (device, name, entry) in found.devices {
+            let id = device.id();
+            let session = match adapter.open(device, self.options.clone()).await {
+                Ok(link) => Session::open(link, self.password(entry.as_ref())).await,
+                Err(e) => Err(e),
+            };
+            let connect_reply = session.as_ref().ok().map(Session::connect_reply);
+            let profile = self.profile(entry.as_ref(), name.as_deref(), connect_reply).to_string();
+            infos.push(DeviceInfo {
+                alias: entry.map(|e| e.alias),
+                name,
+                id,
+                profile,
+                password: session.as_ref().ok().map(Session::is_protected),
+                error: session.err().map(|e| e.to_string()),
+            });
+        }
+        let text = infos.iter().map(|info| {
+            let state = match (&info.error, info.password) {
+                (Some(e), _) => format!("failed: {}", e),
+                (None, Some(true)) => "password protected".to_string(),
+                (None, _) => "no password".to_string(),
+            };
+            let alias = info.alias.as_ref().map(|a| format!("{} ", a)).unwrap_or_default();
+            format!(
+                "{}{} [{}] {}: {}",
+                alias,
+                info.name.as_deref().unwrap_or("(unknown)"),
+                info.id,
+                info.profile,
+                state
+            )
+        });
+        emit(self.global.json, &infos, text.collect::<Vec<_>>())
+    }
+
+    async fn scan(&self) -> Result<(), Box<dyn Error>> {
+        #[derive(Serialize)]
+        struct Seen {
+            name: Option<String>,
+            id: String,
+        }
+
+        let adapter = ble::adapter(self.global.adapter.as_ref()).await?;
+        let matcher = DeviceMatcher::Prefix(self.global.prefix.clone().unwrap_or_default());
+        let mut seen = Vec::new();
+        for device in adapter.find_all(&matcher, &self.options).await? {
+            seen.push(Seen { name: device.name().await, id: device.id() });
+        }
+        let text = seen
+            .iter()
+            .map(|s| format!("{} [{}]", s.name.as_deref().unwrap_or("(unknown)"), s.id));
+        emit(self.global.json, &seen, text.collect::<Vec<_>>())
+    }
+
+    async fn rename(&self, name: &str) -> Result<(), Box<dyn Error>> {
+        let (adapter, device, entry) = self.single().await?;
+        let link = adapter.open(device, self.options.clone()).await?;
+        let mut session = Session::open(link, self.password(entry.as_ref())).await?;
+        session.rename(name).await?;
+        let id = session.id();
+        emit(
+            self.global.json,
+            &serde_json::json!({ "id": id, "name": name }),
+            [format!("{} renamed to {}, the new name shows once the device restarts", id, name)],
+        )
+    }
+
+    async fn discover(&self) -> Result<(), Box<dyn Error>> {
+        let (adapter, device, _) = self.single().await?;
+        let link = adapter.open(device, self.options.clone()).await?;
+        let services = link.services().await?;
+        let mut text = Vec::new();
+        for service in &services {
+            text.push(format!("{}", service.uuid));
+            for charic in &service.characteristics {
+                text.push(format!("  {} {}", charic.uuid, charic.properties.join(",")));
+            }
+        }
+        emit(self.global.json, &services, text)
+    }
+
+    async fn stdin(&self) -> Result<(), Box<dyn Error>> {
+        let (adapter, device, entry) = self.single().await?;
+        let name = device.name().await;
+        let link = adapter.open(device, self.options.clone()).await?;
+        let mut session = Session::open(link, self.password(entry.as_ref())).await?;
+        session.set_profile(self.profile(entry.as_ref(), name.as_deref(), Some(session.connect_reply())));
+        if let Some(level) = entry.as_ref().and_then(|e| e.brightness) {
+            session.brightness(level).await?;
+        }
+        let input = tokio::io::BufReader::new(tokio::io::stdin());
+        let panel = session.profile().panel;
+        let failed = command::run_lines(&mut session, panel, input, tokio::io::stdout()).await?;
+        if failed > 0 {
+            return Err(format!("{} command(s) failed", failed).into());
+        }
+        Ok(())
+    }
+}
+
+// Uploads are followed by the config's brightness, if the device has one.
+fn with_brightness(mut commands: Vec<Command>, entry: Option<&DeviceEntry>) -> Vec<Command> {
+    if let Some(level) = entry.and_then(|e| e.brightness) {
+        commands.push(Command::Brightness { level });
+    }
+    commands
+}
+
+#[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    env_logger::init();
     let cli = Cli::parse();
-
-    let image: ILedImage = match cli {
-        Cli {
-            device_name: _,
-            image_path: Some(path),
-            color: _,
-        } => {
-            let file = File::open(path).map_err(|e| e.to_string())?;
-            ILedImage::from_file(file).map_err(|e| e.to_string())?
-        }
-        Cli {
-            device_name: _,
-            image_path: None,
-            color: Some(color),
-        } => {
-            let (r, g, b) = color.to_rgb();
-            ILedImage::solid_color(48, 12, r, g, b)
-        }
-        _ => panic!("No input provided"),
+    let level = match cli.global.verbose {
+        0 => "warn",
+        1 => "info",
+        2 => "debug",
+        _ => "trace",
     };
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(level)).init();
 
-    println!("Looking for device: {}", cli.device_name);
-    let device = ble::find(&cli.device_name)
-        .await
-        .expect("Scanning failed")
-        .expect("No device found");
+    // Scripts reading --json get failures in the daemon's response shape too, on stdout like everything else.
+    let json = cli.global.json;
+    match run(cli).await {
+        Err(e) if json => {
+            println!("{}", serde_json::to_string(&Response::error(e))?);
+            std::process::exit(1);
+        }
+        result => result,
+    }
+}
 
-    println!("Sending image to device: {}", cli.device_name);
-    send::image(device, image)
-        .await
-        .map_err(|e| e.to_string())?;
-    Ok(())
+async fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    let inventory = match cli.global.config {
+        Some(ref path) => Inventory::load(path)?,
+        None => Inventory::default(),
+    };
+    let options = ConnectOptions {
+        scan_timeout: (cli.global.timeout > 0).then(|| Duration::from_secs(cli.global.timeout)),
+        connect_retries: cli.global.retries,
+        ..Default::default()
+    };
+    let (command, render) = match cli.command {
+        Cmd::Render { out, command } => (Cmd::Draw(command), Some(out)),
+        command => (command, None),
+    };
+    let context = Context { global: cli.global, inventory, options, render };
+
+    match command {
+        Cmd::Draw(DrawCmd::Send { path, fit, filter, letterbox, correction, quantise, frame_delay, effect }) => {
+            let options =
+                FitOptions { fit, filter, letterbox, correction: correction.correction(), quantise: quantise.quantise() };
+            if path.is_dir() {
+                let delay = Duration::from_millis(frame_delay);
+                context
+                    .upload(|profile| {
+                        ILedImage::from_dir(&path, delay, profile.panel, &profile.fit_options(&options))
+                            .map(|image| effect.apply(image))
+                    })
+                    .await
+            } else {
+                let file = std::fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                context
+                    .upload(|profile| {
+                        ILedImage::from_reader(&file[..], profile.panel, &profile.fit_options(&options))
+                            .map(|image| effect.apply(image))
+                    })
+                    .await
+            }
+        }
+        Cmd::Draw(DrawCmd::Color { color, correction, quantise }) => {
+            let options = colour_options(&correction, &quantise);
+            context
+                .upload(|profile| {
+                    Ok::<_, Infallible>(ILedImage::solid_color(profile.panel, color, &profile.fit_options(&options)))
+                })
+                .await
+        }
+        Cmd::Draw(DrawCmd::Text { text, align, correction, quantise, effect }) => {
+            let (style, typeface) = (text.style(align), text.typeface()?);
+            let options = colour_options(&correction, &quantise);
+            context
+                .upload(|profile| {
+                    let options = profile.fit_options(&options);
+                    Ok::<_, Infallible>(effect.apply(text::render(&text.text, &typeface, profile.panel, &style, &options)))
+                })
+                .await
+        }
+        Cmd::Draw(DrawCmd::Marquee { text, speed, direction, gap, correction, quantise }) => {
+            let (style, typeface) = (text.style(Align::Left), text.typeface()?);
+            let marquee = MarqueeOptions { speed, direction, gap };
+            let options = colour_options(&correction, &quantise);
+            context
+                .upload(|profile| {
+                    text::marquee(&text.text, &typeface, profile.panel, &style, &marquee, &profile.fit_options(&options))
+                })
+                .await
+        }
+        Cmd::Draw(DrawCmd::Playlist { items, duration, fit, correction, quantise }) => {
+            let options =
+                FitOptions { correction: correction.correction(), quantise: quantise.quantise(), ..FitOptions::new(fit) };
+            context
+                .upload_data(|profile| {
+                    let options = profile.fit_options(&options);
+                    let mut playlist = Playlist::new();
+                    for (path, seconds) in &items {
+                        let image = ILedImage::from_path(path, profile.panel, &options)
+                            .map_err(|e| format!("{}: {}", path.display(), e))?;
+                        let seconds = Duration::try_from_secs_f64(seconds.unwrap_or(duration)).map_err(|e| e.to_string())?;
+                        playlist.push(image, seconds)?;
+                    }
+                    Ok::<_, String>(playlist.to_ctn_data())
+                })
+                .await
+        }
+        Cmd::Brightness { level } => context.fleet(|_, _| vec![Command::Brightness { level }]).await,
+        Cmd::Power { state } => {
+            let on = matches!(state, PowerState::On);
+            context.fleet(|_, _| vec![Command::Enable { on }]).await
+        }
+        Cmd::Password { op } => {
+            let (old, new) = match op {
+                PasswordCmd::Set { new } => (None, Some(new)),
+                PasswordCmd::Change { old, new } => (Some(old), Some(new)),
+                PasswordCmd::Unset { old } => (Some(old), None),
+            };
+            context
+                .fleet(|_, _| vec![Command::Password { old: old.clone(), new: new.clone() }])
+                .await
+        }
+        Cmd::Info => context.info().await,
+        Cmd::Scan => context.scan().await,
+        Cmd::Rename { name } => context.rename(&name).await,
+        Cmd::Discover => context.discover().await,
+        Cmd::Adapters => {
+            let adapters = ble::adapters().await?;
+            let text = adapters.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+            emit(context.global.json, &adapters, text)
+        }
+        Cmd::Render { .. } => unreachable!("render is taken apart above"),
+        Cmd::Inspect { path, out } => inspect(&path, out),
+        Cmd::Stdin => context.stdin().await,
+    }
 }
@@ -0,0 +1,331 @@
+// MQTT bridge over the daemon's device workers, announced to Home Assistant through MQTT discovery.
+//
+// iledcolor/status                   online/offline, retained, offline is the last will
+// iledcolor/rex/available            online/offline, retained
+// iledcolor/rex/power/set            ON or OFF                          -> iledcolor/rex/power
+// iledcolor/rex/brightness/set       0 (dimmest) to 10 (brightest)      -> iledcolor/rex/brightness
+// iledcolor/rex/rgb/set              255,0,0, #ff0000 or a colour name  -> iledcolor/rex/rgb
+// iledcolor/rex/image/set            file in the image directory, file:// or http(s):// URL if fetching is on
+// iledcolor/rex/result               {"ok": false, "error": "..."} after every command
+use crate::{
+    color::Color,
+    command::Command,
+    config::Panel,
+    daemon::{Daemon, DeviceStatus, Request, Response},
+    fit::FitOptions,
+    image::ILedImage,
+    packet::CtnData,
+};
+use log::{info, warn};
+use rumqttc::{AsyncClient, Event, LastWill, MqttOptions, Packet, QoS};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::mpsc;
+
+const AVAILABILITY_INTERVAL: Duration = Duration::from_secs(5);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+// Largest image file read or fetched for image/set, well past anything that still fits a collar upload.
+pub const MAX_IMAGE_BYTES: u64 = 4 << 20;
+
+#[derive(Debug, Clone)]
+pub struct BridgeOptions {
+    pub host: String,
+    pub port: u16,
+    pub prefix: String,           // first level of every topic, also names the discovery ids
+    pub discovery_prefix: String, // where Home Assistant looks for configs
+    pub credentials: Option<(String, String)>,
+    pub image_dir: Option<PathBuf>, // local images are only read from here, None turns them off
+    pub fetch_urls: bool,           // whether image/set may name an http(s) URL
+}
+
+impl Default for BridgeOptions {
+    fn default() -> Self {
+        BridgeOptions {
+            host: "localhost".to_string(),
+            port: 1883,
+            prefix: "iledcolor".to_string(),
+            discovery_prefix: "homeassistant".to_string(),
+            credentials: None,
+            image_dir: None,
+            fetch_urls: false,
+        }
+    }
+}
+
+// Home Assistant counts brightness up, the device counts 0 (brightest) to 10 (dimmest).
+pub fn brightness_level(payload: &str) -> Result<u8, String> {
+    match payload.parse::<u8>() {
+        Ok(value) if value <= 10 => Ok(10 - value),
+        _ => Err(format!("brightness must be 0 to 10, got {}", payload)),
+    }
+}
+
+fn fetch(url: &str) -> Result<Vec<u8>, String> {
+    let agent: ureq::Agent = ureq::Agent::config_builder().timeout_global(Some(FETCH_TIMEOUT)).build().into();
+    agent
+        .get(url)
+        .call()
+        .and_then(|mut response| response.body_mut().with_config().limit(MAX_IMAGE_BYTES).read_to_vec())
+        .map_err(|e| format!("{}: {}", url, e))
+}
+
+// `name` is taken relative to `dir` and has to stay inside it, symlinks included.
+fn read(dir: &Path, name: &str) -> Result<Vec<u8>, String> {
+    let dir = dir.canonicalize().map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let path = dir.join(name).canonicalize().map_err(|e| format!("{}: {}", name, e))?;
+    if !path.starts_with(&dir) {
+        return Err(format!("{} is outside the image directory", name));
+    }
+    let mut data = Vec::new();
+    File::open(&path)
+        .and_then(|file| file.take(MAX_IMAGE_BYTES + 1).read_to_end(&mut data))
+        .map_err(|e| format!("{}: {}", name, e))?;
+    if data.len() as u64 > MAX_IMAGE_BYTES {
+        return Err(format!("{} is larger than {} bytes", name, MAX_IMAGE_BYTES));
+    }
+    Ok(data)
+}
+
+// Reads or fetches the image and encodes it for the panel, off the async workers.
+async fn image(payload: &str, panel: Panel, options: &BridgeOptions) -> Result<Command, String> {
+    let source = payload.to_string();
+    let dir = options.image_dir.clone();
+    let fetch_urls = options.fetch_urls;
+    tokio::task::spawn_blocking(move || {
+        let file = if source.starts_with("http://") || source.starts_with("https://") {
+            if !fetch_urls {
+                return Err("fetching image URLs is turned off".to_string());
+            }
+            fetch(&source)?
+        } else {
+            let dir = dir.ok_or("local images are turned off, there's no image directory")?;
+            read(&dir, source.strip_prefix("file://").unwrap_or(&source))?
+        };
+        let image = ILedImage::from_reader(&file[..], panel, &FitOptions::default()).map_err(|e| e.to_string())?;
+        Ok(Command::Upload(Arc::new(CtnData::new(image.to_bytes()))))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+pub async fn command(setting: &str, payload: &str, panel: Panel, options: &BridgeOptions) -> Result<Command, String> {
+    match setting {
+        "power" => match payload {
+            "ON" => Ok(Command::Enable { on: true }),
+            "OFF" => Ok(Command::Enable { on: false }),
+            _ => Err(format!("power must be ON or OFF, got {}", payload)),
+        },
+        "brightness" => brightness_level(payload).map(|level| Command::Brightness { level }),
+        "rgb" => payload.parse::<Color>().map(|color| Command::Color { rgb: color.rgb() }),
+        "image" => image(payload, panel, options).await,
+        _ => Err(format!("unknown setting {}", setting)),
+    }
+}
+
+// The retained state a successful command leaves behind, as (setting, payload).
+pub fn state(command: &Command) -> Option<(&'static str, String)> {
+    match command {
+        Command::Enable { on } => Some(("power", if *on { "ON" } else { "OFF" }.to_string())),
+        Command::Brightness { level } => Some(("brightness", (10 - level).to_string())),
+        Command::Color { rgb: [r, g, b] } => Some(("rgb", format!("{},{},{}", r, g, b))),
+        _ => None,
+    }
+}
+
+// Something for the bridge to publish, kept apart from the client so the topic handling can run without a broker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub topic: String,
+    pub retain: bool,
+    pub payload: String,
+}
+
+impl Message {
+    fn new(topic: String, retain: bool, payload: impl Into<String>) -> Self {
+        Message { topic, retain, payload: payload.into() }
+    }
+}
+
+fn topic(options: &BridgeOptions, alias: &str, leaf: &str) -> String {
+    format!("{}/{}/{}", options.prefix, alias, leaf)
+}
+
+// The alias and setting of a `<prefix>/<alias>/<setting>/set` topic.
+pub fn parse_topic<'a>(options: &BridgeOptions, topic: &'a str) -> Option<(&'a str, &'a str)> {
+    let rest = topic.strip_prefix(options.prefix.as_str())?.strip_prefix('/')?;
+    match rest.split('/').collect::<Vec<_>>().as_slice() {
+        [alias, setting, "set"] => Some((alias, setting)),
+        _ => None,
+    }
+}
+
+// The bridge's online status and a Home Assistant light and image text entity per device.
+pub fn discovery(options: &BridgeOptions, devices: &[DeviceStatus]) -> Vec<Message> {
+    let prefix = &options.prefix;
+    let mut messages = vec![Message::new(format!("{}/status", prefix), true, "online")];
+    for device in devices {
+        let alias = &device.alias;
+        let id = format!("{}_{}", prefix, alias);
+        let availability = json!([
+            { "topic": format!("{}/status", prefix) },
+            { "topic": topic(options, alias, "available") },
+        ]);
+        let about = json!({ "identifiers": [id], "name": alias, "model": "iLedColor collar" });
+        let light = json!({
+            "name": null,
+            "unique_id": id,
+            "command_topic": topic(options, alias, "power/set"),
+            "state_topic": topic(options, alias, "power"),
+            "brightness_command_topic": topic(options, alias, "brightness/set"),
+            "brightness_state_topic": topic(options, alias, "brightness"),
+            "brightness_scale": 10,
+            "rgb_command_topic": topic(options, alias, "rgb/set"),
+            "rgb_state_topic": topic(options, alias, "rgb"),
+            "availability": availability,
+            "availability_mode": "all",
+            "device": about,
+        });
+        let image = json!({
+            "name": "Image",
+            "unique_id": format!("{}_image", id),
+            "command_topic": topic(options, alias, "image/set"),
+            "availability": availability,
+            "availability_mode": "all",
+            "device": about,
+        });
+        let discovery = &options.discovery_prefix;
+        messages.push(Message::new(format!("{}/light/{}/config", discovery, id), true, light.to_string()));
+        messages.push(Message::new(format!("{}/text/{}_image/config", discovery, id), true, image.to_string()));
+    }
+    messages
+}
+
+// Runs a `<setting>/set` publish for `alias` on the daemon, answering with the retained state it leaves behind,
+// if any, and the result.
+pub async fn handle(daemon: &Daemon, options: &BridgeOptions, alias: &str, setting: &str, payload: &str) -> Vec<Message> {
+    let panel = daemon.panel(alias).unwrap_or_default();
+    let (response, command) = match command(setting, payload, panel, options).await {
+        Ok(command) => {
+            let request = Request { device: Some(alias.to_string()), command: command.clone() };
+            (daemon.handle(request).await, Some(command))
+        }
+        Err(e) => (Response::error(e), None),
+    };
+    let mut messages = Vec::new();
+    if response.ok
+        && let Some((setting, value)) = command.as_ref().and_then(state)
+    {
+        messages.push(Message::new(topic(options, alias, setting), true, value));
+    }
+    let result = serde_json::to_string(&response).expect("responses always serialize");
+    messages.push(Message::new(topic(options, alias, "result"), false, result));
+    messages
+}
+
+struct Bridge {
+    daemon: Arc<Daemon>,
+    client: AsyncClient,
+    options: BridgeOptions,
+}
+
+impl Bridge {
+    async fn publish(&self, message: Message) {
+        if let Err(e) = self.client.publish(message.topic, QoS::AtLeastOnce, message.retain, message.payload).await {
+            warn!("mqtt: publish failed: {}", e);
+        }
+    }
+
+    async fn announce(&self) {
+        if let Err(e) = self.client.subscribe(format!("{}/+/+/set", self.options.prefix), QoS::AtLeastOnce).await {
+            warn!("mqtt: subscribe failed: {}", e);
+        }
+        for message in discovery(&self.options, &self.daemon.status()) {
+            self.publish(message).await;
+        }
+    }
+
+    async fn run(&self, alias: String, setting: String, payload: String) {
+        for message in handle(&self.daemon, &self.options, &alias, &setting, &payload).await {
+            self.publish(message).await;
+        }
+    }
+}
+
+// Publishes each device's connection state whenever it changes, and everything again after `forget`.
+async fn availability(bridge: Arc<Bridge>, published: Arc<Mutex<HashMap<String, bool>>>) {
+    let mut interval = tokio::time::interval(AVAILABILITY_INTERVAL);
+    loop {
+        interval.tick().await;
+        for device in bridge.daemon.status() {
+            let changed = published.lock().unwrap().insert(device.alias.clone(), device.connected) != Some(device.connected);
+            if changed {
+                let payload = if device.connected { "online" } else { "offline" };
+                bridge.publish(Message::new(topic(&bridge.options, &device.alias, "available"), true, payload)).await;
+            }
+        }
+    }
+}
+
+// Runs until the process exits, reconnecting to the broker whenever the connection drops.
+// Commands to one device run in the order they arrive, different devices don't wait on each other.
+pub async fn bridge(daemon: Arc<Daemon>, options: BridgeOptions) {
+    let mut mqtt = MqttOptions::new(format!("{}-bridge", options.prefix), &options.host, options.port);
+    mqtt.set_keep_alive(Duration::from_secs(30));
+    mqtt.set_last_will(LastWill::new(format!("{}/status", options.prefix), "offline", QoS::AtLeastOnce, true));
+    if let Some((user, password)) = &options.credentials {
+        mqtt.set_credentials(user, password);
+    }
+    let (client, mut eventloop) = AsyncClient::new(mqtt, 64);
+    let bridge = Arc::new(Bridge { daemon, client, options });
+
+    let mut queues = HashMap::new();
+    for device in bridge.daemon.status() {
+        let (queue, mut rx) = mpsc::unbounded_channel::<(String, String)>();
+        let worker = bridge.clone();
+        let alias = device.alias.clone();
+        tokio::spawn(async move {
+            while let Some((setting, payload)) = rx.recv().await {
+                worker.run(alias.clone(), setting, payload).await;
+            }
+        });
+        queues.insert(device.alias, queue);
+    }
+    let published = Arc::new(Mutex::new(HashMap::new()));
+    tokio::spawn(availability(bridge.clone(), published.clone()));
+
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                info!("mqtt: connected to {}:{}", bridge.options.host, bridge.options.port);
+                published.lock().unwrap().clear();
+                let announcer = bridge.clone();
+                tokio::spawn(async move { announcer.announce().await });
+            }
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                let Some((alias, setting)) = parse_topic(&bridge.options, &publish.topic) else {
+                    continue;
+                };
+                let payload = String::from_utf8_lossy(&publish.payload).trim().to_string();
+                match queues.get(alias) {
+                    Some(queue) => {
+                        let _ = queue.send((setting.to_string(), payload));
+                    }
+                    None => warn!("mqtt: no device with alias {}", alias),
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                warn!("mqtt: {}, reconnecting in {:?}", e, RECONNECT_DELAY);
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
@@ -1,5 +1,6 @@
+use crate::error::Error;
 use crc::{CRC_32_ISCSI, Crc};
-use std::{ffi::os_str::Display, mem::size_of};
+use std::mem::size_of;
 use strum_macros::{self, FromRepr, Display};
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, FromRepr, Display)]
@@ -15,6 +16,7 @@ pub enum Handle {           // TODO find remaining handles, maybe with fuzzing?
     Unknown,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub enum Data {         // TODO integrate this enum with Packet, write a generic Impl for it.
     Gen(Vec<u8>),
@@ -22,7 +24,7 @@ pub enum Data {         // TODO integrate this enum with Packet, write a generic
     End(u8),
     Sta(StaData),
     Con(u8),
-    POp{opcode:u8, old_Pass:[u8;6], new_Pass:[u8;6]},
+    POp{opcode:u8, old_pass:[u8;6], new_pass:[u8;6]},
     Pas([u8;6]),
 }
 
@@ -172,27 +174,60 @@ pub struct Notification {
     checksum: u16, // Sum of all bytes in packet
 }
 impl Notification {
-    // TODO add comparisons and errors for opcode; handle; length; checksum.
-    // TODO refactor return into a Result<> so upstream can choose how to handle things, not all errors are critical
-    pub fn from_vec_u8(response: Vec<u8>) -> Self {
-        let handle = Handle::from_repr(response[1]).unwrap();
-        Notification { 
+    pub fn data(&self) -> &NotificationType {
+        &self.data
+    }
+
+    // Checks the marker, length and checksum before looking at the data. Handles and result codes nobody has seen
+    // yet come back as their Unknown variants, it's up to the caller whether that's an error.
+    pub fn from_vec_u8(response: Vec<u8>) -> Result<Self, Error> {
+        let garbled = |message: String| Error::BadNotification(message, response.clone());
+        if response.len() < 6 {
+            return Err(garbled(format!("{} bytes is too short", response.len())));
+        }
+        if response[0] != 0x54 {
+            return Err(garbled(format!("starts with 0x{:02x} instead of 0x54", response[0])));
+        }
+        let length = u16::from_be_bytes([response[2], response[3]]);
+        if length as usize != response.len() - 4 {
+            return Err(garbled(format!("announces {} bytes after its length but has {}", length, response.len() - 4)));
+        }
+        let (body, end) = response.split_at(response.len() - 2);
+        let checksum = u16::from_be_bytes([end[0], end[1]]);
+        let sum = body.iter().fold(0u16, |sum, b| sum.wrapping_add(*b as u16));
+        if sum != checksum {
+            return Err(garbled(format!("checksum 0x{:04x} doesn't match the bytes' 0x{:04x}", checksum, sum)));
+        }
+
+        let handle = Handle::from_repr(response[1]).unwrap_or(Handle::Unknown);
+        let data = &body[4..];
+        let min_len = match handle {
+            Handle::Continue => 4,
+            Handle::Connect => 2,
+            Handle::Unknown => 0,
+            _ => 1,
+        };
+        if data.len() < min_len {
+            return Err(garbled(format!("{} bytes of data is too short for a {} reply", data.len(), handle)));
+        }
+        let gen_res = || GenRes::from_repr(data[0]).unwrap_or(GenRes::Unknown);
+        Ok(Notification {
             opcode: response[0],
             handle,
-            length: u16::from_be_bytes(response[2..4].try_into().unwrap()),
+            length,
             data: match handle {
-                Handle::Continue => NotificationType::Continue{chunk: response[7]},
-                Handle::EndStream => NotificationType::EndStream(GenRes::from_repr(response[4]).unwrap()),
-                Handle::StartStream => NotificationType::StartStream{chunks: response[4]},
-                Handle::Brightness => NotificationType::Brightness(GenRes::from_repr(response[4]).unwrap()),
-                Handle::LedEnable => NotificationType::LedEnable(GenRes::from_repr(response[4]).unwrap()),
-                Handle::Connect => NotificationType::Connect(response[4..6].try_into().unwrap()),
-                Handle::TestPass => NotificationType::TestPass(TestPassRes::from_repr(response[4]).unwrap()),
-                Handle::SetPass => NotificationType::SetPass(GenRes::from_repr(response[4]).unwrap()),
-                _ => NotificationType::Unknown(response[4..response.len()-2].into()),
+                Handle::Continue => NotificationType::Continue{chunk: data[3]},
+                Handle::EndStream => NotificationType::EndStream(gen_res()),
+                Handle::StartStream => NotificationType::StartStream{chunks: data[0]},
+                Handle::Brightness => NotificationType::Brightness(gen_res()),
+                Handle::LedEnable => NotificationType::LedEnable(gen_res()),
+                Handle::Connect => NotificationType::Connect([data[0], data[1]]),
+                Handle::TestPass => NotificationType::TestPass(TestPassRes::from_repr(data[0]).unwrap_or(TestPassRes::Unknown)),
+                Handle::SetPass => NotificationType::SetPass(gen_res()),
+                Handle::Unknown => NotificationType::Unknown(data.into()),
             },
-            checksum: u16::from_be_bytes(response[response.len()-2 ..].try_into().unwrap()),
-        }
+            checksum,
+        })
     }
 }
 
@@ -211,38 +246,116 @@ impl std::fmt::Display for Notification {
 }
 
 // 01 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00
+// The first byte is 01 in every capture, taken to be the number of programs (images) that follow.
 
 #[derive(Debug, Clone)]
 pub struct CtnData {
     pub crc32: u32,
-    start_byte: u8, // 01
+    programs: u8,
     padding: [u8; 19],
     pub data: Vec<u8>,
 }
 
-const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+pub const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
 
 impl CtnData {
     pub fn new(data: Vec<u8>) -> Self {
+        Self::with_programs(1, data)
+    }
+
+    // `data` holding `programs` images back to back, see playlist.rs.
+    pub fn with_programs(programs: u8, data: Vec<u8>) -> Self {
         let crc32 = CRC32.checksum(&data);
         CtnData {
             crc32,
-            start_byte: 0x01,
+            programs,
             padding: [0; 19],
             data,
         }
     }
 
+    // Reads back what `to_bytes` wrote, rejecting data that doesn't match its checksum.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 24 {
+            return Err(format!("{} bytes is too short for the 24 byte stream header", bytes.len()));
+        }
+        let crc32 = u32::from_be_bytes(bytes[0..4].try_into().unwrap());
+        let data = bytes[24..].to_vec();
+        let checksum = CRC32.checksum(&data);
+        if checksum != crc32 {
+            return Err(format!("checksum 0x{:08x} doesn't match the data's 0x{:08x}", crc32, checksum));
+        }
+        Ok(CtnData {
+            crc32,
+            programs: bytes[4],
+            padding: bytes[5..24].try_into().unwrap(),
+            data,
+        })
+    }
+
+    pub fn programs(&self) -> u8 {
+        self.programs
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
         bytes.extend(&self.crc32.to_be_bytes());
-        bytes.push(self.start_byte);
+        bytes.push(self.programs);
         bytes.extend(&self.padding);
         bytes.extend(&self.data);
         bytes
     }
 }
 
+// Six ascii digits, an all-zero password is what the device treats as "no password".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Password(pub [u8; 6]);
+
+impl std::str::FromStr for Password {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes: [u8; 6] = s
+            .as_bytes()
+            .try_into()
+            .map_err(|_| format!("password must be 6 digits, got {} characters", s.len()))?;
+        if !bytes.iter().all(u8::is_ascii_digit) {
+            return Err("password must only contain digits".to_string());
+        }
+        Ok(Password(bytes))
+    }
+}
+
+// Password operations 0x0E: opcode, old password, new password.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordOp {
+    Set(Password),
+    Change { old: Password, new: Password },
+    Unset(Password),
+}
+
+impl PasswordOp {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (opcode, old, new) = match *self {
+            PasswordOp::Set(new) => (0x00, Password::default(), new),
+            PasswordOp::Change { old, new } => (0x01, old, new),
+            PasswordOp::Unset(old) => (0x02, old, Password::default()),
+        };
+        let mut bytes = vec![opcode];
+        bytes.extend(old.0);
+        bytes.extend(new.0);
+        bytes
+    }
+
+    // The password the device expects once the operation went through.
+    pub fn result(&self) -> Password {
+        match *self {
+            PasswordOp::Set(new) | PasswordOp::Change { new, .. } => new,
+            PasswordOp::Unset(_) => Password::default(),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct StaData {
     pub crc32: u32,
@@ -0,0 +1,72 @@
+// Several images the device cycles through, each shown for its own duration. A playlist goes up as a single stream
+// with the program count where a single upload has its 01, followed by the images back to back, each with its own
+// header and the duration in its hold field. Nobody has captured the official app sending one, so this layout is
+// extrapolated from the single image captures and unconfirmed, see docs/ouppy.md, and the CLI only sends playlists
+// with --experimental.
+use crate::{image::ILedImage, packet::CtnData};
+use image::{
+    ImageError,
+    error::{DecodingError, ImageFormatHint},
+};
+use std::time::Duration;
+
+// The program count is a single byte.
+pub const MAX_PROGRAMS: usize = u8::MAX as usize;
+// The hold field is guessed to count tenths of a second, the official app's 0x0032 being its 5 second default. The
+// GIF capture's 0x0400 doesn't fit that reading.
+const HOLD_UNIT: Duration = Duration::from_millis(100);
+
+#[derive(Default)]
+pub struct Playlist {
+    programs: Vec<ILedImage>,
+}
+
+impl Playlist {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, image: ILedImage, duration: Duration) -> Result<(), String> {
+        if self.programs.len() == MAX_PROGRAMS {
+            return Err(format!("a playlist holds at most {} images", MAX_PROGRAMS));
+        }
+        let hold = (duration.as_millis() / HOLD_UNIT.as_millis()).max(1);
+        let hold = u16::try_from(hold)
+            .map_err(|_| format!("{}s is longer than an image can be shown", duration.as_secs()))?;
+        self.programs.push(image.with_hold(hold));
+        Ok(())
+    }
+
+    // Splits a stream back into its programs, which keep their hold times.
+    pub fn from_ctn_data(ctn: &CtnData) -> Result<Self, ImageError> {
+        let mut programs = Vec::new();
+        let mut at = 0;
+        for _ in 0..ctn.programs() {
+            let (image, len) = ILedImage::read(&ctn.data[at..])?;
+            programs.push(image);
+            at += len;
+        }
+        if at != ctn.data.len() {
+            let message = format!("{} bytes left over after {} programs", ctn.data.len() - at, programs.len());
+            return Err(ImageError::Decoding(DecodingError::new(ImageFormatHint::Unknown, message)));
+        }
+        Ok(Playlist { programs })
+    }
+
+    pub fn programs(&self) -> &[ILedImage] {
+        &self.programs
+    }
+
+    pub fn len(&self) -> usize {
+        self.programs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.programs.is_empty()
+    }
+
+    pub fn to_ctn_data(&self) -> CtnData {
+        let data = self.programs.iter().flat_map(ILedImage::to_bytes).collect();
+        CtnData::with_programs(self.programs.len() as u8, data)
+    }
+}
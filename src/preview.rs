@@ -0,0 +1,46 @@
+// Shows panel images in a true-colour terminal, two pixel rows per character: an upper half block drawn with the
+// top pixel as its colour and the pixel below as its background.
+use image::RgbImage;
+use std::{io::Write, time::Duration};
+
+const UPPER_HALF: char = '▀';
+const RESET: &str = "\x1b[0m";
+
+// Terminal lines `image` takes up.
+pub fn lines(image: &RgbImage) -> u32 {
+    image.height().div_ceil(2)
+}
+
+pub fn half_blocks(image: &RgbImage) -> String {
+    let mut out = String::new();
+    for y in (0..image.height()).step_by(2) {
+        for x in 0..image.width() {
+            let [r, g, b] = image.get_pixel(x, y).0;
+            out += &format!("\x1b[38;2;{};{};{}m", r, g, b);
+            // an odd last row has nothing below it, the terminal's own background shows instead
+            match image.get_pixel_checked(x, y + 1) {
+                Some(below) => out += &format!("\x1b[48;2;{};{};{}m", below[0], below[1], below[2]),
+                None => out += "\x1b[49m",
+            }
+            out.push(UPPER_HALF);
+        }
+        out += RESET;
+        out.push('\n');
+    }
+    out
+}
+
+// Plays `frames` once, each over the one before, waiting its delay before moving on.
+pub async fn play(out: &mut impl Write, frames: &[(RgbImage, Duration)]) -> std::io::Result<()> {
+    for (i, (frame, delay)) in frames.iter().enumerate() {
+        if i > 0 {
+            write!(out, "\x1b[{}A", lines(frame))?;
+        }
+        write!(out, "{}", half_blocks(frame))?;
+        out.flush()?;
+        if i + 1 < frames.len() {
+            tokio::time::sleep(*delay).await;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,124 @@
+// What an iLED product's panel can show and take. Only the collar has been measured, other products are expected to
+// speak the same protocol with other panel sizes and can be described as a custom WIDTHxHEIGHT profile until they
+// get one of their own here.
+// Products are told apart by their advertised name and, once connected, by their reply to the Connect handshake.
+// The handshake carries nothing else about the device. The official app also exchanges a device info packet
+// (fedcba..., see "Early connection packets" in docs/ouppy.md), but that isn't decoded yet, so it can't be used.
+use crate::{
+    config::Panel,
+    error::Error,
+    fit::FitOptions,
+    image::Mode,
+    packet::CtnData,
+    playlist::Playlist,
+    quantise::{Levels, Quantise},
+};
+use std::{fmt, str::FromStr};
+
+// StartStream announces the stream length in 16 bits.
+pub const MAX_PAYLOAD: usize = u16::MAX as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PanelProfile {
+    pub name: &'static str,
+    pub panel: Panel,
+    pub bits: u8,           // colour depth per channel
+    pub max_payload: usize, // longest upload stream, header included
+    pub modes: &'static [Mode],
+    pub advertised: &'static [&'static str], // prefixes of the names the product advertises
+    pub connect_reply: [u8; 2],              // what the product answers the Connect handshake with
+}
+
+pub const COLLAR: PanelProfile = PanelProfile {
+    name: "collar",
+    panel: Panel { width: 48, height: 12 },
+    bits: 8,
+    max_payload: MAX_PAYLOAD,
+    modes: &[Mode::Still, Mode::Animation],
+    advertised: &["iLedColor"],
+    connect_reply: [0x00, 0x00],
+};
+
+pub const PROFILES: &[PanelProfile] = &[COLLAR];
+
+impl PanelProfile {
+    pub fn named(name: &str) -> Option<Self> {
+        PROFILES.iter().copied().find(|profile| profile.name.eq_ignore_ascii_case(name))
+    }
+
+    // The profile of the product advertising `name`, if it's a known one. Before connecting there's only the name,
+    // afterwards the reply to the handshake has to match as well.
+    pub fn detect(name: &str, connect_reply: Option<[u8; 2]>) -> Option<Self> {
+        PROFILES.iter().copied().find(|profile| {
+            profile.advertised.iter().any(|prefix| name.starts_with(prefix))
+                && connect_reply.is_none_or(|reply| reply == profile.connect_reply)
+        })
+    }
+
+    // The built-in profile with this panel size, or a custom one that otherwise behaves like the collar.
+    pub fn for_panel(panel: Panel) -> Self {
+        PROFILES
+            .iter()
+            .copied()
+            .find(|profile| profile.panel == panel)
+            .unwrap_or(PanelProfile { name: "custom", panel, advertised: &[], ..COLLAR })
+    }
+
+    // `options` reduced to the panel's colour depth, unless they already ask for fewer colours.
+    pub fn fit_options(&self, options: &FitOptions) -> FitOptions {
+        let mut options = options.clone();
+        if options.quantise.is_identity() && self.bits < 8 {
+            options.quantise = Quantise { levels: Levels::Bits(self.bits), ..options.quantise };
+        }
+        options
+    }
+
+    // Rejects uploads the panel can't take, before anything is sent.
+    pub fn check(&self, data: &CtnData) -> Result<(), String> {
+        self.check_len(data).map_err(|e| format!("{} for the {} profile", e, self.name))?;
+        let playlist = Playlist::from_ctn_data(data).map_err(|e| e.to_string())?;
+        for program in playlist.programs() {
+            let metadata = program.metadata();
+            if !self.modes.contains(&metadata.mode()) {
+                return Err(format!("the {} profile doesn't show {} images", self.name, metadata.mode()));
+            }
+        }
+        Ok(())
+    }
+
+    // The part of `check` sessions repeat before streaming, whatever built the upload.
+    pub fn check_len(&self, data: &CtnData) -> Result<(), Error> {
+        let len = data.to_bytes().len();
+        match len > self.max_payload {
+            true => Err(Error::TooLarge(len, self.max_payload)),
+            false => Ok(()),
+        }
+    }
+}
+
+impl Default for PanelProfile {
+    fn default() -> Self {
+        COLLAR
+    }
+}
+
+impl fmt::Display for PanelProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}x{}", self.name, self.panel.width, self.panel.height)
+    }
+}
+
+// A profile name or a panel size, e.g. collar or 64x16.
+impl FromStr for PanelProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(profile) = Self::named(s) {
+            return Ok(profile);
+        }
+        let names: Vec<_> = PROFILES.iter().map(|profile| profile.name).collect();
+        s.parse()
+            .map(Self::for_panel)
+            .map_err(|_| format!("unknown panel {}, use {} or WIDTHxHEIGHT", s, names.join(", ")))
+    }
+}
@@ -0,0 +1,117 @@
+// Reduces colours for panels, or modes, with less than 8 bits per channel: to a number of evenly spaced levels per
+// channel or to a fixed palette, optionally dithered so gradients survive as patterns.
+use crate::color::Color;
+use image::RgbImage;
+
+// 4x4 Bayer matrix, thresholds from 0 to 15 spread so neighbouring pixels differ as much as possible.
+pub(crate) const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dither {
+    // every pixel to its nearest colour, flat areas stay flat
+    None,
+    // the rounding error carried on to the neighbours, finest for photos
+    #[default]
+    FloydSteinberg,
+    // a fixed threshold pattern, stable from one animation frame to the next
+    Bayer,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Levels {
+    #[default]
+    Full,
+    // bits per channel, 1 to 8
+    Bits(u8),
+    Palette(Vec<Color>),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Quantise {
+    pub levels: Levels,
+    pub dither: Dither,
+}
+
+impl Quantise {
+    pub fn is_identity(&self) -> bool {
+        match &self.levels {
+            Levels::Full => true,
+            Levels::Bits(bits) => *bits >= 8,
+            Levels::Palette(colours) => colours.is_empty(),
+        }
+    }
+
+    // The representable colour nearest to `rgb`, whose channels may be out of range after dithering.
+    fn nearest(&self, rgb: [f32; 3]) -> [u8; 3] {
+        match &self.levels {
+            Levels::Full => rgb.map(|c| c.round().clamp(0.0, 255.0) as u8),
+            Levels::Bits(_) => {
+                let step = self.spread();
+                rgb.map(|c| ((c / step).round() * step).clamp(0.0, 255.0) as u8)
+            }
+            Levels::Palette(colours) => colours
+                .iter()
+                .map(|colour| colour.rgb())
+                .min_by(|a, b| distance(rgb, *a).total_cmp(&distance(rgb, *b)))
+                .unwrap_or([0; 3]),
+        }
+    }
+
+    // Roughly the gap between neighbouring colours, how far ordered dithering pushes a channel.
+    fn spread(&self) -> f32 {
+        match &self.levels {
+            Levels::Full => 1.0,
+            Levels::Bits(bits) => 255.0 / ((1u32 << (*bits).clamp(1, 8)) - 1) as f32,
+            Levels::Palette(colours) => 255.0 / (colours.len() as f32).cbrt().max(2.0),
+        }
+    }
+
+    pub fn apply(&self, image: &mut RgbImage) {
+        if self.is_identity() {
+            return;
+        }
+        let (width, height) = image.dimensions();
+        match self.dither {
+            Dither::None => {
+                for pixel in image.pixels_mut() {
+                    pixel.0 = self.nearest(pixel.0.map(f32::from));
+                }
+            }
+            Dither::Bayer => {
+                let spread = self.spread();
+                for (x, y, pixel) in image.enumerate_pixels_mut() {
+                    let offset = ((BAYER[y as usize % 4][x as usize % 4] as f32 + 0.5) / 16.0 - 0.5) * spread;
+                    pixel.0 = self.nearest(pixel.0.map(|c| c as f32 + offset));
+                }
+            }
+            Dither::FloydSteinberg => {
+                let mut values: Vec<[f32; 3]> = image.pixels().map(|pixel| pixel.0.map(f32::from)).collect();
+                let index = |x: u32, y: u32| (y * width + x) as usize;
+                for y in 0..height {
+                    for x in 0..width {
+                        let old = values[index(x, y)];
+                        let new = self.nearest(old);
+                        image.put_pixel(x, y, image::Rgb(new));
+                        let error = [0, 1, 2].map(|c| old[c] - new[c] as f32);
+                        let mut spread = |x: i64, y: u32, weight: f32| {
+                            if x >= 0 && (x as u32) < width && y < height {
+                                let value = &mut values[index(x as u32, y)];
+                                for c in 0..3 {
+                                    value[c] += error[c] * weight;
+                                }
+                            }
+                        };
+                        spread(x as i64 + 1, y, 7.0 / 16.0);
+                        spread(x as i64 - 1, y + 1, 3.0 / 16.0);
+                        spread(x as i64, y + 1, 5.0 / 16.0);
+                        spread(x as i64 + 1, y + 1, 1.0 / 16.0);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn distance(a: [f32; 3], b: [u8; 3]) -> f32 {
+    (0..3).map(|c| (a[c] - b[c] as f32).powi(2)).sum()
+}
@@ -1,10 +1,7 @@
 use std::time::Duration;
-use crate::{ble::{self, ILEDDev, find}, image::ILedImage, packet::{CtnData, Handle, Notification, Packet, StaData}};
-use bluest::{Adapter, BluetoothUuidExt, Characteristic, Device, DeviceId, Service, Uuid, btuuid};
-use image::EncodableLayout;
-use log::{debug, info};
+use crate::{ble::{GattService, Link}, error::Error, image::ILedImage, packet::{CtnData, GenRes, Handle, Notification, NotificationType, Packet, Password, PasswordOp, StaData, TestPassRes}, profile::PanelProfile};
+use log::{debug, info, warn};
 use tokio::time::sleep;
-use tokio_stream::StreamExt;
 
 pub fn print_bytes_hex(message: &str, bytes: &[u8]) { // TODO overhaul error handling and logging to use bluest errors again
     let mut output = String::new();
@@ -16,132 +13,242 @@ pub fn print_bytes_hex(message: &str, bytes: &[u8]) { // TODO overhaul error han
     debug!("{}", output);
 }
 
-// TODO refactor image into a pile of helper/sending functions with response and error handling.
-pub async fn image(device: Device, image: ILedImage) -> Result<(), std::io::Error> {
-    let dev = ILEDDev::new(device).await;
-    
-    debug!("Subscribing to notifications...");
-    let mut updates = dev.notify_char
-        .notify()
-        .await
-        .expect("Failed to subscribe to notifications");
-    // let mut response: Result<Vec<u8>, bluest::Error>;
-    
-    // 54 0d 0003 00 0064
-    let connect_packet = Packet::new(
-        None, 
-        Handle::Connect, 
-        None, 
-        None, 
-        vec![0x00]);
-    dev.cmd_char
-        .write_without_response(&connect_packet.to_bytes())
-        .await
-        .expect("Failed to write to characteristic 1");
-    print_bytes_hex("Connect Packet 1", &connect_packet.to_bytes());
-    let response = Notification::from_vec_u8(
-        updates
-        .next()
-        .await
-        .expect("No response")
-        .expect("Invalid response")
-    );
-    info!("{}", response);
-    sleep(Duration::from_millis(10)).await;
-    
-    // 54 0f 0008 00 00 00 00 00 00 006b
-    let auth_reset_packet = Packet::new(
-        None,
-        Handle::TestPass,
-        None,
-        None,
-        vec![0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
-    );
-    dev.cmd_char
-        .write_without_response(&auth_reset_packet.to_bytes())
-        .await
-        .expect("Failed to write to characteristic 1");
-    print_bytes_hex("Connect Packet 2", &auth_reset_packet.to_bytes());
-    let response = Notification::from_vec_u8(updates
-        .next()
-        .await
-        .expect("No response")
-        .expect("Invalid response")
-    );
-    info!("{}", response);
-    // sleep(Duration::from_millis(100)).await;
-    
-    
-    let img_data = CtnData::new(image.to_bytes());
-    
-    let begin_data = StaData::new(
-        img_data.crc32,
-        img_data.to_bytes().len() as u16 
-    );
-    
-    let begin_packet = Packet::new(
-        None,
-        Handle::StartStream,
-        None,
-        None,
-        begin_data.to_bytes()
-    );
-    print_bytes_hex("Begin Packet", &begin_packet.to_bytes());
-    dev.cmd_char
-        .write_without_response(&begin_packet.to_bytes())
-        .await
-        .expect("Failed to write to characteristic 1");
-    let response = Notification::from_vec_u8(updates
-        .next()
-        .await
-        .expect("No response")
-        .expect("Invalid response")
-    );
-    info!("{}", response);
-    sleep(tokio::time::Duration::from_millis(10)).await;
-    
-    for (index, chunk) in img_data.to_bytes().chunks(492).enumerate() {
-        let packet = Packet::new(
+pub struct Session {
+    link: Link,
+    password: Password,
+    protected: bool, // whether the device asked for a password during the last handshake
+    connect_reply: [u8; 2],
+    profile: PanelProfile, // what uploads are checked against, the collar unless told otherwise
+}
+
+impl Session {
+    pub async fn open(link: Link, password: Option<Password>) -> Result<Self, Error> {
+        let mut session = Session {
+            link,
+            password: password.unwrap_or_default(),
+            protected: false,
+            connect_reply: [0; 2],
+            profile: PanelProfile::default(),
+        };
+        session.handshake().await?;
+        Ok(session)
+    }
+
+    // The device is expected to be `profile`, a different handshake reply is logged.
+    pub fn set_profile(&mut self, profile: PanelProfile) {
+        if self.connect_reply != profile.connect_reply {
+            warn!("{} answered the handshake with {:02x?}, not like a {}", self.id(), self.connect_reply, profile);
+        }
+        self.profile = profile;
+    }
+
+    pub fn profile(&self) -> PanelProfile {
+        self.profile
+    }
+
+    // What the device answered the Connect packet with during the last handshake.
+    pub fn connect_reply(&self) -> [u8; 2] {
+        self.connect_reply
+    }
+
+    pub fn id(&self) -> String {
+        self.link.id()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.link.is_connected()
+    }
+
+    pub fn is_protected(&self) -> bool {
+        self.protected
+    }
+
+    // The password handshakes use, None when the device has none.
+    pub fn password_in_use(&self) -> Option<Password> {
+        (self.password != Password::default()).then_some(self.password)
+    }
+
+    pub async fn reconnect(&mut self) -> Result<(), Error> {
+        self.link.reconnect().await?;
+        self.handshake().await
+    }
+
+    pub async fn rename(&mut self, name: &str) -> Result<(), Error> {
+        self.link.rename(name).await
+    }
+
+    pub async fn services(&self) -> Result<Vec<GattService>, Error> {
+        self.link.services().await
+    }
+
+    async fn command(&mut self, packet: &Packet) -> Result<Notification, Error> {
+        self.link.write_command(&packet.to_bytes()).await?;
+        self.response().await
+    }
+
+    async fn data(&mut self, packet: &Packet) -> Result<Notification, Error> {
+        self.link.write_data(&packet.to_bytes()).await?;
+        self.response().await
+    }
+
+    async fn response(&mut self) -> Result<Notification, Error> {
+        let response = Notification::from_vec_u8(self.link.notification().await?)?;
+        info!("{}", response);
+        Ok(response)
+    }
+
+    async fn handshake(&mut self) -> Result<(), Error> {
+        // 54 0d 0003 00 0064
+        let connect_packet = Packet::new(
+            None,
+            Handle::Connect,
             None,
-            Handle::Continue,
-            Some(index as u32),
-            Some(chunk.len() as u16),
-            chunk.to_vec(),
+            None,
+            vec![0x00]);
+        print_bytes_hex("Connect Packet 1", &connect_packet.to_bytes());
+        if let NotificationType::Connect(reply) = self.command(&connect_packet).await?.data() {
+            self.connect_reply = *reply;
+        }
+        sleep(Duration::from_millis(10)).await;
+
+        // 54 0f 0008 00 00 00 00 00 00 006b
+        let auth_packet = Packet::new(
+            None,
+            Handle::TestPass,
+            None,
+            None,
+            self.password.0.to_vec(),
         );
-        print_bytes_hex("Image Data Packet Chunk:", &packet.to_bytes());
-        dev.write_char
-            .write_without_response(&packet.to_bytes())
-            .await
-            .expect("Failed to write to characteristic 1");
-        let response = Notification::from_vec_u8(updates
-            .next()
-            .await
-            .expect("No response")
-            .expect("Invalid response")
+        print_bytes_hex("Connect Packet 2", &auth_packet.to_bytes());
+        let response = self.command(&auth_packet).await?;
+        match response.data() {
+            NotificationType::TestPass(TestPassRes::Incorrect) => return Err(Error::WrongPassword),
+            NotificationType::TestPass(result) => self.protected = matches!(result, TestPassRes::Correct),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Repeats the password check, a command without side effects that shows whether the device still answers.
+    pub async fn ping(&mut self) -> Result<(), Error> {
+        let packet = Packet::new(None, Handle::TestPass, None, None, self.password.0.to_vec());
+        match self.command(&packet).await?.data() {
+            NotificationType::TestPass(TestPassRes::Incorrect) => Err(Error::WrongPassword),
+            NotificationType::TestPass(_) => Ok(()),
+            _ => Err(Error::Rejected(Handle::TestPass)),
+        }
+    }
+
+    // 0x00/0x01 are the brightest and 0x0A the dimmest, see the Dimming table in ouppy.md.
+    pub async fn brightness(&mut self, level: u8) -> Result<(), Error> {
+        let mut data = vec![0x00; 9];
+        data[0] = level;
+        let packet = Packet::new(None, Handle::Brightness, None, None, data);
+        print_bytes_hex("Brightness Packet", &packet.to_bytes());
+        match self.command(&packet).await?.data() {
+            NotificationType::Brightness(GenRes::Success) => Ok(()),
+            _ => Err(Error::Rejected(Handle::Brightness)),
+        }
+    }
+
+    pub async fn enable(&mut self, on: bool) -> Result<(), Error> {
+        let mut data = vec![0x00; 9];
+        data[0] = on as u8;
+        let packet = Packet::new(None, Handle::LedEnable, None, None, data);
+        print_bytes_hex("Enable Packet", &packet.to_bytes());
+        match self.command(&packet).await?.data() {
+            NotificationType::LedEnable(GenRes::Success) => Ok(()),
+            _ => Err(Error::Rejected(Handle::LedEnable)),
+        }
+    }
+
+    // Later reconnects of this session authenticate with the new password.
+    pub async fn password(&mut self, op: PasswordOp) -> Result<(), Error> {
+        let packet = Packet::new(None, Handle::SetPass, None, None, op.to_bytes());
+        print_bytes_hex("Password Packet", &packet.to_bytes());
+        match self.command(&packet).await?.data() {
+            NotificationType::SetPass(GenRes::Success) => {
+                self.password = op.result();
+                self.protected = self.password != Password::default();
+                Ok(())
+            }
+            _ => Err(Error::Rejected(Handle::SetPass)),
+        }
+    }
+
+    async fn stream(&mut self, img_data: &CtnData) -> Result<(), Error> {
+        let bytes = img_data.to_bytes();
+        let len = u16::try_from(bytes.len()).map_err(|_| Error::TooLarge(bytes.len(), u16::MAX as usize))?;
+        let begin_data = StaData::new(
+            img_data.crc32,
+            len
         );
-        info!("{}", response);
-        // sleep(tokio::time::Duration::from_millis(10)).await;
-    }
-    
-    let end_packet = Packet::new(
-        None, 
-        Handle::EndStream,
-        None,
-        None,
-        vec![0x01]
-    );
-    print_bytes_hex("End Packet", &end_packet.to_bytes());
-    dev.write_char
-        .write_without_response(&end_packet.to_bytes())
-        .await
-        .expect("Failed to write to characteristic 1");
-    let response = Notification::from_vec_u8(updates
-        .next()
-        .await
-        .expect("No response")
-        .expect("Invalid response")
-    );
-    info!("{}", response);
-    
-    Ok(())
+
+        let begin_packet = Packet::new(
+            None,
+            Handle::StartStream,
+            None,
+            None,
+            begin_data.to_bytes()
+        );
+        print_bytes_hex("Begin Packet", &begin_packet.to_bytes());
+        match self.command(&begin_packet).await?.data() {
+            NotificationType::StartStream { .. } => {}
+            _ => return Err(Error::Rejected(Handle::StartStream)),
+        }
+        sleep(Duration::from_millis(10)).await;
+
+        for (index, chunk) in bytes.chunks(492).enumerate() {
+            let packet = Packet::new(
+                None,
+                Handle::Continue,
+                Some(index as u32),
+                Some(chunk.len() as u16),
+                chunk.to_vec(),
+            );
+            print_bytes_hex("Image Data Packet Chunk:", &packet.to_bytes());
+            // the device acknowledges every chunk with its number
+            match self.data(&packet).await?.data() {
+                NotificationType::Continue { chunk } if *chunk == index as u8 => {}
+                _ => return Err(Error::Rejected(Handle::Continue)),
+            }
+        }
+
+        let end_packet = Packet::new(
+            None,
+            Handle::EndStream,
+            None,
+            None,
+            vec![0x01]
+        );
+        print_bytes_hex("End Packet", &end_packet.to_bytes());
+        match self.data(&end_packet).await?.data() {
+            NotificationType::EndStream(GenRes::Success) => Ok(()),
+            _ => Err(Error::Rejected(Handle::EndStream)),
+        }
+    }
+
+    // The device isn't known to keep a partial stream across connections,
+    // so a dropped upload, or one the device stopped answering, is restarted from the StartStream packet after
+    // reconnecting.
+    pub async fn upload(&mut self, img_data: &CtnData) -> Result<(), Error> {
+        self.profile.check_len(img_data)?;
+        let retries = self.link.options().connect_retries;
+        let mut attempt = 0;
+        loop {
+            match self.stream(img_data).await {
+                Err(e @ (Error::Disconnected | Error::NoResponse)) if attempt < retries => {
+                    attempt += 1;
+                    warn!("{} during upload, resuming ({}/{})", e, attempt, retries);
+                    self.reconnect().await?;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+pub async fn image(link: Link, image: ILedImage, password: Option<Password>) -> Result<(), Error> {
+    let mut session = Session::open(link, password).await?;
+    session.upload(&CtnData::new(image.to_bytes())).await
 }
@@ -0,0 +1,311 @@
+// In-memory stand-in for a collar, answering packets the way ouppy.md describes so sessions can run without bluetooth.
+// Where the real behaviour is unknown (e.g. commands sent before authenticating) this errs on the strict side.
+use crate::{
+    ble::{
+        ConnectOptions, GattCharacteristic, GattService, CMD_CHARIC_UUID, NOTIFY_CHARIC_UUID, WRITE_CHARIC_UUID, WRITE_SERVICE_UUID,
+        _DEVICE_NAME_UUID, _GENERIC_SERVICE_UUID,
+    },
+    error::Error,
+    packet::{GenRes, Handle, Packet, Password, TestPassRes, CRC32},
+};
+use log::debug;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::mpsc;
+
+#[derive(Debug, Clone, Default)]
+pub struct SimState {
+    pub password: Password,
+    pub authenticated: bool,
+    pub brightness: u8,
+    pub enabled: bool,
+    pub connected: bool,
+    pub out_of_range: bool, // connecting fails until it's back
+    pub name: Option<String>, // set by a rename, None keeps the id
+    pub uploads: Vec<Vec<u8>>, // completed CtnData payloads, header included
+    pub streams_started: usize, // StartStream packets accepted, restarted uploads included
+    drop_after: Option<usize>, // Continue chunks still taken before the connection drops
+    ignored_replies: Option<(Handle, usize)>, // replies to a handle swallowed while the connection stays up
+    stream: Option<(u32, usize, Vec<u8>)>, // crc32, announced length, received bytes
+}
+
+impl SimState {
+    fn unlocked(&self) -> bool {
+        self.password == Password::default() || self.authenticated
+    }
+
+    fn gen_res(ok: bool) -> u8 {
+        if ok { GenRes::Success as u8 } else { GenRes::Fail as u8 }
+    }
+
+    // Returns the notification payload for a packet, None for packets the device ignores.
+    fn handle(&mut self, bytes: &[u8]) -> Option<(Handle, Vec<u8>)> {
+        if bytes.len() < 6 || bytes[0] != 0x54 {
+            return None;
+        }
+        let checksum = bytes[..bytes.len() - 2]
+            .iter()
+            .fold(0u16, |sum, b| sum.wrapping_add(*b as u16));
+        if checksum.to_be_bytes() != bytes[bytes.len() - 2..] {
+            debug!("sim: dropping packet with bad checksum");
+            return None;
+        }
+        let handle = Handle::from_repr(bytes[1])?;
+        let data = &bytes[4..bytes.len() - 2];
+        let min_len = match handle {
+            Handle::TestPass => 6,
+            Handle::SetPass => 13,
+            Handle::StartStream => 11,
+            Handle::Continue => 6,
+            _ => 1,
+        };
+        if data.len() < min_len {
+            debug!("sim: dropping short {} packet", handle);
+            return None;
+        }
+        let response = match handle {
+            Handle::Connect => vec![0x00, 0x00],
+            Handle::TestPass => {
+                let result = if self.password == Password::default() {
+                    TestPassRes::NoPass
+                } else if data == self.password.0 {
+                    self.authenticated = true;
+                    TestPassRes::Correct
+                } else {
+                    TestPassRes::Incorrect
+                };
+                vec![result as u8]
+            }
+            Handle::SetPass => {
+                let (op, old, new) = (data[0], &data[1..7], &data[7..13]);
+                let ok = match op {
+                    0x00 => self.password == Password::default(),
+                    0x01 | 0x02 => old == self.password.0,
+                    _ => false,
+                };
+                if ok {
+                    self.password = match op {
+                        0x02 => Password::default(),
+                        _ => Password(new.try_into().unwrap()),
+                    };
+                }
+                vec![Self::gen_res(ok)]
+            }
+            Handle::Brightness => {
+                let ok = self.unlocked();
+                if ok {
+                    self.brightness = data[0];
+                }
+                vec![Self::gen_res(ok)]
+            }
+            Handle::LedEnable => {
+                let ok = self.unlocked();
+                if ok {
+                    self.enabled = data[0] != 0;
+                }
+                vec![Self::gen_res(ok)]
+            }
+            Handle::StartStream => {
+                let crc = u32::from_be_bytes(data[0..4].try_into().unwrap());
+                let len = u16::from_be_bytes(data[6..8].try_into().unwrap()) as usize;
+                self.stream = self.unlocked().then(|| (crc, len, Vec::with_capacity(len)));
+                self.streams_started += 1;
+                vec![(len.div_ceil(492).saturating_sub(1)) as u8]
+            }
+            Handle::Continue => {
+                if let Some(left) = self.drop_after {
+                    self.drop_after = left.checked_sub(1);
+                    if left == 0 {
+                        self.connected = false;
+                        return None;
+                    }
+                }
+                // sequence and data length sit between the header and the data
+                let sequence = u32::from_be_bytes(bytes[4..8].try_into().unwrap());
+                if let Some((_, _, buf)) = &mut self.stream {
+                    buf.extend(&bytes[10..bytes.len() - 2]);
+                }
+                vec![0x00, 0x00, 0x00, sequence as u8, 0x01]
+            }
+            Handle::EndStream => {
+                let ok = match self.stream.take() {
+                    Some((crc, len, buf)) if buf.len() == len && buf.len() >= 24 => {
+                        let ok = CRC32.checksum(&buf[24..]) == crc;
+                        if ok {
+                            self.uploads.push(buf);
+                        }
+                        ok
+                    }
+                    _ => false,
+                };
+                vec![Self::gen_res(ok)]
+            }
+            Handle::Unknown => return None,
+        };
+        Some((handle, response))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SimDevice(Arc<Mutex<SimState>>);
+
+impl SimDevice {
+    pub fn state(&self) -> SimState {
+        self.0.lock().unwrap().clone()
+    }
+
+    // Drops the connection the way a collar walking out of range would.
+    pub fn drop_connection(&self) {
+        self.0.lock().unwrap().connected = false;
+    }
+
+    // Takes `chunks` more Continue packets, then drops the connection in the middle of the stream.
+    pub fn drop_after_chunks(&self, chunks: usize) {
+        self.0.lock().unwrap().drop_after = Some(chunks);
+    }
+
+    // Handles the next `count` packets for `handle` without answering them, like replies lost on the air.
+    pub fn ignore_replies(&self, handle: Handle, count: usize) {
+        self.0.lock().unwrap().ignored_replies = Some((handle, count));
+    }
+
+    pub fn set_out_of_range(&self, out_of_range: bool) {
+        let mut state = self.0.lock().unwrap();
+        state.out_of_range = out_of_range;
+        state.connected &= !out_of_range;
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Simulator {
+    devices: Arc<Mutex<HashMap<String, SimDevice>>>,
+}
+
+impl Simulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&self, id: &str, password: Option<Password>) -> SimDevice {
+        let device = SimDevice::default();
+        device.0.lock().unwrap().password = password.unwrap_or_default();
+        device.0.lock().unwrap().enabled = true;
+        self.devices
+            .lock()
+            .unwrap()
+            .insert(id.to_string(), device.clone());
+        device
+    }
+
+    pub fn device(&self, id: &str) -> Option<SimDevice> {
+        self.devices.lock().unwrap().get(id).cloned()
+    }
+
+    pub fn ids(&self) -> Vec<String> {
+        self.devices.lock().unwrap().keys().cloned().collect()
+    }
+
+    pub fn open(&self, id: &str, options: ConnectOptions) -> Result<SimLink, Error> {
+        let device = self.device(id).ok_or_else(|| Error::NotFound(id.to_string()))?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut link = SimLink { id: id.to_string(), device, tx, updates: rx, options };
+        link.connect()?;
+        Ok(link)
+    }
+}
+
+// The services a real collar reports, minus the unknown 0xae00 one.
+pub fn services() -> Vec<GattService> {
+    let charic = |uuid, properties: &[&'static str]| GattCharacteristic { uuid, properties: properties.to_vec() };
+    vec![
+        GattService {
+            uuid: _GENERIC_SERVICE_UUID,
+            characteristics: vec![charic(_DEVICE_NAME_UUID, &["read", "write"])],
+        },
+        GattService {
+            uuid: WRITE_SERVICE_UUID,
+            characteristics: vec![
+                charic(CMD_CHARIC_UUID, &["write-without-response"]),
+                charic(WRITE_CHARIC_UUID, &["write-without-response"]),
+                charic(NOTIFY_CHARIC_UUID, &["notify"]),
+            ],
+        },
+    ]
+}
+
+pub struct SimLink {
+    id: String,
+    device: SimDevice,
+    tx: mpsc::UnboundedSender<Vec<u8>>,
+    updates: mpsc::UnboundedReceiver<Vec<u8>>,
+    options: ConnectOptions,
+}
+
+impl SimLink {
+    fn connect(&mut self) -> Result<(), Error> {
+        let mut state = self.device.0.lock().unwrap();
+        if state.out_of_range {
+            return Err(Error::NotFound(self.id.clone()));
+        }
+        state.connected = true;
+        state.authenticated = false;
+        state.stream = None;
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.tx = tx;
+        self.updates = rx;
+        Ok(())
+    }
+
+    pub fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    pub fn options(&self) -> &ConnectOptions {
+        &self.options
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.device.0.lock().unwrap().connected
+    }
+
+    // Commands and data share one state machine, the real device splits them over two characteristics.
+    pub async fn write(&self, bytes: &[u8]) -> Result<(), Error> {
+        let mut state = self.device.0.lock().unwrap();
+        if !state.connected {
+            return Err(Error::Disconnected);
+        }
+        if let Some((handle, data)) = state.handle(bytes) {
+            if let Some((ignored, count)) = &mut state.ignored_replies
+                && *ignored as u8 == handle as u8
+                && *count > 0
+            {
+                *count -= 1;
+                return Ok(());
+            }
+            let _ = self.tx.send(Packet::new(None, handle, None, None, data).to_bytes());
+        }
+        Ok(())
+    }
+
+    pub async fn notification(&mut self) -> Result<Vec<u8>, Error> {
+        if !self.is_connected() {
+            return Err(Error::Disconnected);
+        }
+        self.updates.recv().await.ok_or(Error::NoResponse)
+    }
+
+    pub fn rename(&self, name: &str) -> Result<(), Error> {
+        let mut state = self.device.0.lock().unwrap();
+        if !state.connected {
+            return Err(Error::Disconnected);
+        }
+        state.name = Some(name.to_string());
+        Ok(())
+    }
+
+    pub async fn reconnect(&mut self) -> Result<(), Error> {
+        self.connect()
+    }
+}
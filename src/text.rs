@@ -0,0 +1,307 @@
+// Text for the panel: strings are rasterised into a coverage mask, then drawn in the foreground colour over the
+// background, either still or scrolling through as a marquee.
+use crate::{
+    color::Color,
+    config::Panel,
+    fit::{self, FitOptions},
+    font::OutlineFont,
+    image::ILedImage,
+};
+use image::{Delay, DynamicImage, Frame, GrayImage, Luma, RgbImage};
+
+pub const GLYPH_HEIGHT: u32 = 7;
+const SPACE_WIDTH: u32 = 3;
+const LETTER_SPACING: u32 = 1;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Align {
+    Left,
+    #[default]
+    Center,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextStyle {
+    pub foreground: Color,
+    pub background: Color,
+    pub align: Align,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle { foreground: Color::WHITE, background: Color::BLACK, align: Align::Center }
+    }
+}
+
+// Columns of a glyph with the blank ones on either side trimmed, so narrow letters take less room.
+// Characters the font doesn't have are drawn as '?'.
+fn glyph(c: char) -> &'static [u8] {
+    let index = match c {
+        ' '..='~' => c as usize - ' ' as usize,
+        _ => '?' as usize - ' ' as usize,
+    };
+    let columns = &FONT_5X7[index];
+    let first = columns.iter().position(|c| *c != 0).unwrap_or(0);
+    let last = columns.iter().rposition(|c| *c != 0).unwrap_or(0);
+    &columns[first..=last]
+}
+
+// Width in pixels of `text` in the bundled 5x7 font.
+pub fn text_width(text: &str) -> u32 {
+    let widths: Vec<u32> = text
+        .chars()
+        .map(|c| if c == ' ' { SPACE_WIDTH } else { glyph(c).len() as u32 })
+        .collect();
+    widths.iter().sum::<u32>() + LETTER_SPACING * (widths.len().saturating_sub(1) as u32)
+}
+
+// Rasterises `text` in the bundled 5x7 font, 255 where the glyphs are lit.
+pub fn pixel_mask(text: &str) -> GrayImage {
+    let mut mask = GrayImage::new(text_width(text).max(1), GLYPH_HEIGHT);
+    let mut x = 0;
+    for c in text.chars() {
+        if c == ' ' {
+            x += SPACE_WIDTH + LETTER_SPACING;
+            continue;
+        }
+        for column in glyph(c) {
+            for y in 0..GLYPH_HEIGHT {
+                if column & (1 << y) != 0 {
+                    mask.put_pixel(x, y, Luma([255]));
+                }
+            }
+            x += 1;
+        }
+        x += LETTER_SPACING;
+    }
+    mask
+}
+
+// Where the letters come from, the built-in pixel font unless a TrueType/OpenType font is loaded.
+#[derive(Default)]
+pub enum Typeface {
+    #[default]
+    Pixel,
+    Outline(OutlineFont),
+}
+
+impl Typeface {
+    pub fn mask(&self, text: &str) -> GrayImage {
+        match self {
+            Typeface::Pixel => pixel_mask(text),
+            Typeface::Outline(font) => font.mask(text),
+        }
+    }
+}
+
+// Left edge of something `width` wide aligned on a panel, negative when it overflows to the left.
+pub fn aligned_x(width: u32, panel_width: u32, align: Align) -> i64 {
+    match align {
+        Align::Left => 0,
+        Align::Center => (panel_width as i64 - width as i64) / 2,
+        Align::Right => panel_width as i64 - width as i64,
+    }
+}
+
+fn blend(background: u8, foreground: u8, coverage: u8) -> u8 {
+    let coverage = coverage as u32;
+    ((background as u32 * (255 - coverage) + foreground as u32 * coverage + 127) / 255) as u8
+}
+
+// Draws `mask` onto a panel sized image at `x`, centred vertically. Whatever falls outside the panel is cut off.
+pub fn compose(mask: &GrayImage, panel: Panel, x: i64, style: &TextStyle) -> RgbImage {
+    let (width, height) = (panel.width as u32, panel.height as u32);
+    let mut image = RgbImage::from_pixel(width, height, image::Rgb(style.background.rgb()));
+    let y = (height as i64 - mask.height() as i64) / 2;
+    for (mx, my, Luma([coverage])) in mask.enumerate_pixels() {
+        let (px, py) = (x + mx as i64, y + my as i64);
+        if *coverage == 0 || px < 0 || py < 0 || px >= width as i64 || py >= height as i64 {
+            continue;
+        }
+        let [r, g, b] = style.background.rgb();
+        let [fr, fg, fb] = style.foreground.rgb();
+        image.put_pixel(
+            px as u32,
+            py as u32,
+            image::Rgb([blend(r, fr, *coverage), blend(g, fg, *coverage), blend(b, fb, *coverage)]),
+        );
+    }
+    image
+}
+
+// A still image of `text`, aligned on the panel and cut off where it doesn't fit. Only the correction and
+// quantisation of `fit` matter, the text is drawn at the panel's size.
+pub fn render(text: &str, typeface: &Typeface, panel: Panel, style: &TextStyle, fit: &FitOptions) -> ILedImage {
+    let mask = typeface.mask(text);
+    let x = aligned_x(mask.width(), panel.width as u32, style.align);
+    ILedImage::from_rgb(&fit::fit(&compose(&mask, panel, x, style), panel, fit))
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Direction {
+    // text moves towards the left edge, the way it reads
+    #[default]
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarqueeOptions {
+    pub speed: u32, // pixels per second
+    pub direction: Direction,
+    pub gap: Option<u32>, // blank pixels before the text comes round again, the panel width if not set
+}
+
+impl Default for MarqueeOptions {
+    fn default() -> Self {
+        MarqueeOptions { speed: 20, direction: Direction::Left, gap: None }
+    }
+}
+
+// GIF delays count in hundredths of a second and players stretch anything shorter than two of them,
+// so faster scrolls move more than one pixel per frame instead.
+const MIN_FRAME_DELAY_MS: u32 = 20;
+
+// Scrolls `text` across the panel as a looping GIF, one full pass of the text and gap per loop.
+pub fn marquee(
+    text: &str,
+    typeface: &Typeface,
+    panel: Panel,
+    style: &TextStyle,
+    options: &MarqueeOptions,
+    fit: &FitOptions,
+) -> Result<ILedImage, image::ImageError> {
+    let text_mask = typeface.mask(text);
+    let panel_width = panel.width as u32;
+    let speed = options.speed.max(1);
+    let step = (speed * MIN_FRAME_DELAY_MS).div_ceil(1000).max(1);
+    // Padding the gap to a whole number of steps keeps the loop seamless.
+    let period = (text_mask.width() + options.gap.unwrap_or(panel_width)).next_multiple_of(step);
+
+    // The text enters from the edge it moves away from.
+    let start = match options.direction {
+        Direction::Left => period as i64 - panel_width as i64,
+        Direction::Right => text_mask.width() as i64,
+    };
+    let delay = Delay::from_numer_denom_ms(step * 1000, speed);
+    let frames = (0..period / step)
+        .map(|frame| {
+            let moved = (frame * step) as i64;
+            let offset = match options.direction {
+                Direction::Left => start + moved,
+                Direction::Right => start - moved,
+            };
+            let window = GrayImage::from_fn(panel_width, text_mask.height(), |x, y| {
+                let column = (x as i64 + offset).rem_euclid(period as i64) as u32;
+                match column < text_mask.width() {
+                    true => *text_mask.get_pixel(column, y),
+                    false => Luma([0]),
+                }
+            });
+            let rgba = DynamicImage::ImageRgb8(compose(&window, panel, 0, style)).into_rgba8();
+            Frame::from_parts(rgba, 0, 0, delay)
+        })
+        .collect();
+    ILedImage::from_frames(frames, panel, fit)
+}
+
+// The classic 5x7 LCD font for ' ' to '~', one byte per column with the top row in the lowest bit.
+#[rustfmt::skip]
+const FONT_5X7: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5f, 0x00, 0x00], // '!'
+    [0x00, 0x07, 0x00, 0x07, 0x00], // '"'
+    [0x14, 0x7f, 0x14, 0x7f, 0x14], // '#'
+    [0x24, 0x2a, 0x7f, 0x2a, 0x12], // '$'
+    [0x23, 0x13, 0x08, 0x64, 0x62], // '%'
+    [0x36, 0x49, 0x55, 0x22, 0x50], // '&'
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '''
+    [0x00, 0x1c, 0x22, 0x41, 0x00], // '('
+    [0x00, 0x41, 0x22, 0x1c, 0x00], // ')'
+    [0x08, 0x2a, 0x1c, 0x2a, 0x08], // '*'
+    [0x08, 0x08, 0x3e, 0x08, 0x08], // '+'
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ','
+    [0x08, 0x08, 0x08, 0x08, 0x08], // '-'
+    [0x00, 0x60, 0x60, 0x00, 0x00], // '.'
+    [0x20, 0x10, 0x08, 0x04, 0x02], // '/'
+    [0x3e, 0x51, 0x49, 0x45, 0x3e], // '0'
+    [0x00, 0x42, 0x7f, 0x40, 0x00], // '1'
+    [0x42, 0x61, 0x51, 0x49, 0x46], // '2'
+    [0x21, 0x41, 0x45, 0x4b, 0x31], // '3'
+    [0x18, 0x14, 0x12, 0x7f, 0x10], // '4'
+    [0x27, 0x45, 0x45, 0x45, 0x39], // '5'
+    [0x3c, 0x4a, 0x49, 0x49, 0x30], // '6'
+    [0x01, 0x71, 0x09, 0x05, 0x03], // '7'
+    [0x36, 0x49, 0x49, 0x49, 0x36], // '8'
+    [0x06, 0x49, 0x49, 0x29, 0x1e], // '9'
+    [0x00, 0x36, 0x36, 0x00, 0x00], // ':'
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ';'
+    [0x08, 0x14, 0x22, 0x41, 0x00], // '<'
+    [0x14, 0x14, 0x14, 0x14, 0x14], // '='
+    [0x00, 0x41, 0x22, 0x14, 0x08], // '>'
+    [0x02, 0x01, 0x51, 0x09, 0x06], // '?'
+    [0x32, 0x49, 0x79, 0x41, 0x3e], // '@'
+    [0x7e, 0x11, 0x11, 0x11, 0x7e], // 'A'
+    [0x7f, 0x49, 0x49, 0x49, 0x36], // 'B'
+    [0x3e, 0x41, 0x41, 0x41, 0x22], // 'C'
+    [0x7f, 0x41, 0x41, 0x22, 0x1c], // 'D'
+    [0x7f, 0x49, 0x49, 0x49, 0x41], // 'E'
+    [0x7f, 0x09, 0x09, 0x09, 0x01], // 'F'
+    [0x3e, 0x41, 0x49, 0x49, 0x7a], // 'G'
+    [0x7f, 0x08, 0x08, 0x08, 0x7f], // 'H'
+    [0x00, 0x41, 0x7f, 0x41, 0x00], // 'I'
+    [0x20, 0x40, 0x41, 0x3f, 0x01], // 'J'
+    [0x7f, 0x08, 0x14, 0x22, 0x41], // 'K'
+    [0x7f, 0x40, 0x40, 0x40, 0x40], // 'L'
+    [0x7f, 0x02, 0x0c, 0x02, 0x7f], // 'M'
+    [0x7f, 0x04, 0x08, 0x10, 0x7f], // 'N'
+    [0x3e, 0x41, 0x41, 0x41, 0x3e], // 'O'
+    [0x7f, 0x09, 0x09, 0x09, 0x06], // 'P'
+    [0x3e, 0x41, 0x51, 0x21, 0x5e], // 'Q'
+    [0x7f, 0x09, 0x19, 0x29, 0x46], // 'R'
+    [0x46, 0x49, 0x49, 0x49, 0x31], // 'S'
+    [0x01, 0x01, 0x7f, 0x01, 0x01], // 'T'
+    [0x3f, 0x40, 0x40, 0x40, 0x3f], // 'U'
+    [0x1f, 0x20, 0x40, 0x20, 0x1f], // 'V'
+    [0x3f, 0x40, 0x38, 0x40, 0x3f], // 'W'
+    [0x63, 0x14, 0x08, 0x14, 0x63], // 'X'
+    [0x07, 0x08, 0x70, 0x08, 0x07], // 'Y'
+    [0x61, 0x51, 0x49, 0x45, 0x43], // 'Z'
+    [0x00, 0x7f, 0x41, 0x41, 0x00], // '['
+    [0x02, 0x04, 0x08, 0x10, 0x20], // '\'
+    [0x00, 0x41, 0x41, 0x7f, 0x00], // ']'
+    [0x04, 0x02, 0x01, 0x02, 0x04], // '^'
+    [0x40, 0x40, 0x40, 0x40, 0x40], // '_'
+    [0x00, 0x01, 0x02, 0x04, 0x00], // '`'
+    [0x20, 0x54, 0x54, 0x54, 0x78], // 'a'
+    [0x7f, 0x48, 0x44, 0x44, 0x38], // 'b'
+    [0x38, 0x44, 0x44, 0x44, 0x20], // 'c'
+    [0x38, 0x44, 0x44, 0x48, 0x7f], // 'd'
+    [0x38, 0x54, 0x54, 0x54, 0x18], // 'e'
+    [0x08, 0x7e, 0x09, 0x01, 0x02], // 'f'
+    [0x0c, 0x52, 0x52, 0x52, 0x3e], // 'g'
+    [0x7f, 0x08, 0x04, 0x04, 0x78], // 'h'
+    [0x00, 0x44, 0x7d, 0x40, 0x00], // 'i'
+    [0x20, 0x40, 0x44, 0x3d, 0x00], // 'j'
+    [0x7f, 0x10, 0x28, 0x44, 0x00], // 'k'
+    [0x00, 0x41, 0x7f, 0x40, 0x00], // 'l'
+    [0x7c, 0x04, 0x18, 0x04, 0x78], // 'm'
+    [0x7c, 0x08, 0x04, 0x04, 0x78], // 'n'
+    [0x38, 0x44, 0x44, 0x44, 0x38], // 'o'
+    [0x7c, 0x14, 0x14, 0x14, 0x08], // 'p'
+    [0x08, 0x14, 0x14, 0x18, 0x7c], // 'q'
+    [0x7c, 0x08, 0x04, 0x04, 0x08], // 'r'
+    [0x48, 0x54, 0x54, 0x54, 0x20], // 's'
+    [0x04, 0x3f, 0x44, 0x40, 0x20], // 't'
+    [0x3c, 0x40, 0x40, 0x20, 0x7c], // 'u'
+    [0x1c, 0x20, 0x40, 0x20, 0x1c], // 'v'
+    [0x3c, 0x40, 0x30, 0x40, 0x3c], // 'w'
+    [0x44, 0x28, 0x10, 0x28, 0x44], // 'x'
+    [0x0c, 0x50, 0x50, 0x50, 0x3c], // 'y'
+    [0x44, 0x64, 0x54, 0x4c, 0x44], // 'z'
+    [0x00, 0x08, 0x36, 0x41, 0x00], // '{'
+    [0x00, 0x00, 0x7f, 0x00, 0x00], // '|'
+    [0x00, 0x41, 0x36, 0x08, 0x00], // '}'
+    [0x08, 0x04, 0x08, 0x10, 0x08], // '~'
+];
@@ -0,0 +1,115 @@
+use image::{
+    AnimationDecoder, Delay, Frame, RgbImage, RgbaImage,
+    codecs::gif::{GifDecoder, GifEncoder},
+};
+use iledcolor_rs::{animation, config::Panel, fit::FitOptions, image::ILedImage};
+use std::{io::Cursor, time::Duration};
+
+const PANEL: Panel = Panel { width: 48, height: 12 };
+// The GIF follows the 22 byte image header.
+const GIF: usize = 22;
+
+// A GIF the way most tools write one: local palettes, a single loop and no frame delay.
+fn gif(width: u32, height: u32, frames: &[[u8; 4]]) -> Vec<u8> {
+    let mut data = Vec::new();
+    {
+        let mut encoder = GifEncoder::new(&mut data);
+        let frames = frames.iter().map(|colour| {
+            Frame::from_parts(RgbaImage::from_pixel(width, height, image::Rgba(*colour)), 0, 0, Delay::from_numer_denom_ms(0, 1))
+        });
+        encoder.encode_frames(frames).unwrap();
+    }
+    data
+}
+
+fn frames(image: &ILedImage) -> Vec<Frame> {
+    let bytes = image.to_bytes();
+    GifDecoder::new(Cursor::new(&bytes[GIF..])).unwrap().into_frames().collect_frames().unwrap()
+}
+
+#[test]
+fn reencodes_oversized_gifs() {
+    let file = gif(60, 30, &[[255, 0, 0, 255], [0, 0, 255, 255]]);
+    assert!(!animation::conforms(&file, PANEL));
+    let image = ILedImage::from_reader(&file[..], PANEL, &FitOptions::default()).unwrap();
+    assert!(animation::conforms(&image.to_bytes()[GIF..], PANEL));
+
+    let frames = frames(&image);
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].buffer().dimensions(), (48, 12));
+    // Frames without a delay get the one players default to.
+    assert_eq!(frames[0].delay().numer_denom_ms(), (100, 1));
+    // The 2:1 source is letterboxed inside the 4:1 panel.
+    assert_eq!(frames[1].buffer().get_pixel(0, 6).0, [0, 0, 0, 255]);
+    assert_eq!(frames[1].buffer().get_pixel(24, 6).0, [0, 0, 255, 255]);
+}
+
+#[test]
+fn sends_conforming_gifs_unchanged() {
+    let frames: Vec<_> = [[0, 255, 0], [255, 255, 0]]
+        .into_iter()
+        .map(|colour| (RgbImage::from_pixel(48, 12, image::Rgb(colour)), 5))
+        .collect();
+    let file = animation::encode(&frames, PANEL).unwrap();
+    assert!(animation::conforms(&file, PANEL));
+    let image = ILedImage::from_reader(&file[..], PANEL, &FitOptions::default()).unwrap();
+    assert_eq!(&image.to_bytes()[GIF..], &file[..]);
+}
+
+#[test]
+fn quantises_true_colour_to_one_palette() {
+    let gradient = RgbImage::from_fn(48, 12, |x, y| image::Rgb([(x * 5) as u8, (y * 20) as u8, ((x + y) * 3) as u8]));
+    let shifted = RgbImage::from_fn(48, 12, |x, y| image::Rgb([(y * 20) as u8, (x * 5) as u8, 128]));
+    let file = animation::encode(&[(gradient, 10), (shifted, 10)], PANEL).unwrap();
+    assert!(animation::conforms(&file, PANEL));
+
+    let decoder = gif::DecodeOptions::new().read_info(&file[..]).unwrap();
+    assert!(decoder.global_palette().unwrap().len() <= 256 * 3);
+}
+
+// An animated PNG of solid frames, each shown for `delay_ms`.
+fn apng(colours: &[[u8; 3]], delay_ms: u16) -> Vec<u8> {
+    let mut data = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut data, 24, 6);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_animated(colours.len() as u32, 0).unwrap();
+        encoder.set_frame_delay(delay_ms, 1000).unwrap();
+        let mut writer = encoder.write_header().unwrap();
+        for colour in colours {
+            let frame: Vec<u8> = colour.repeat(24 * 6);
+            writer.write_image_data(&frame).unwrap();
+        }
+    }
+    data
+}
+
+#[test]
+fn decodes_animated_pngs() {
+    let file = apng(&[[255, 0, 0], [0, 255, 0], [0, 0, 255]], 50);
+    let image = ILedImage::from_reader(&file[..], PANEL, &FitOptions::default()).unwrap();
+    assert!(animation::conforms(&image.to_bytes()[GIF..], PANEL));
+
+    let frames = frames(&image);
+    assert_eq!(frames.len(), 3);
+    assert_eq!(frames[0].delay().numer_denom_ms(), (50, 1));
+    assert_eq!(frames[2].buffer().get_pixel(24, 6).0, [0, 0, 255, 255]);
+}
+
+#[test]
+fn orders_frame_directories_by_number() {
+    let dir = std::env::temp_dir().join(format!("iledcolor-frames-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    // frame10 sorts before frame9 by name, the frame number puts it last.
+    for (name, colour) in [("frame10.png", [0, 0, 255]), ("frame9.png", [0, 255, 0]), ("frame1.png", [255, 0, 0])] {
+        RgbImage::from_pixel(48, 12, image::Rgb(colour)).save(dir.join(name)).unwrap();
+    }
+    std::fs::write(dir.join("notes.txt"), "not a frame").unwrap();
+
+    let image = ILedImage::from_dir(&dir, Duration::from_millis(200), PANEL, &FitOptions::default());
+    std::fs::remove_dir_all(&dir).unwrap();
+    let frames = frames(&image.unwrap());
+    let colours: Vec<_> = frames.iter().map(|frame| frame.buffer().get_pixel(0, 0).0).collect();
+    assert_eq!(colours, [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]]);
+    assert_eq!(frames[0].delay().numer_denom_ms(), (200, 1));
+}
@@ -0,0 +1,61 @@
+use iledcolor_rs::color::Color;
+
+#[test]
+fn parses_valid_colours() {
+    let cases = [
+        ("#ff8800", [255, 136, 0]),
+        ("#FF8800", [255, 136, 0]),
+        ("#f80", [255, 136, 0]),
+        ("  #000  ", [0, 0, 0]),
+        ("rgb(255, 0, 0)", [255, 0, 0]),
+        ("RGB(0 128 255)", [0, 128, 255]),
+        ("rgb (1,2,3)", [1, 2, 3]),
+        ("rgb(100%, 50%, 0%)", [255, 128, 0]),
+        ("hsv(0, 100%, 100%)", [255, 0, 0]),
+        ("hsv(120deg, 100%, 100%)", [0, 255, 0]),
+        ("hsv(240, 100, 50)", [0, 0, 128]),
+        ("hsv(-120, 100%, 100%)", [0, 0, 255]),
+        ("hsv(0, 0%, 100%)", [255, 255, 255]),
+        ("255,0,0", [255, 0, 0]),
+        ("10, 20, 30", [10, 20, 30]),
+        ("red", [255, 0, 0]),
+        ("RebeccaPurple", [102, 51, 153]),
+        ("grey", [128, 128, 128]),
+    ];
+    for (input, rgb) in cases {
+        assert_eq!(input.parse::<Color>().map(Color::rgb), Ok(rgb), "{}", input);
+    }
+}
+
+#[test]
+fn rejects_invalid_colours() {
+    let cases = [
+        ("#ff88", "3 or 6 digits"),
+        ("#ff88001", "3 or 6 digits"),
+        ("#", "3 or 6 digits"),
+        ("#gg0000", "invalid hex"),
+        ("#ééé", "invalid hex"),
+        ("rgb(256, 0, 0)", "0 to 255"),
+        ("rgb(-1, 0, 0)", "0 to 255"),
+        ("rgb(101%, 0%, 0%)", "out of range"),
+        ("rgb(1, 2)", "three values"),
+        ("rgb(1, 2, 3, 4)", "three values"),
+        ("hsv(0, 150%, 100%)", "out of range"),
+        ("hsv(red, 100%, 100%)", "invalid hue"),
+        ("hsv(0, 50%, x)", "invalid number"),
+        ("1,2", "three values"),
+        ("300,0,0", "0 to 255"),
+        ("blurple", "unknown colour"),
+        ("", "unknown colour"),
+    ];
+    for (input, error) in cases {
+        let message = input.parse::<Color>().unwrap_err();
+        assert!(message.contains(error), "{:?} gave {:?}", input, message);
+    }
+}
+
+#[test]
+fn displays_as_hex() {
+    assert_eq!(Color::new(255, 136, 0).to_string(), "#ff8800");
+    assert_eq!(Color::WHITE.to_string().parse(), Ok(Color::WHITE));
+}
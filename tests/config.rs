@@ -0,0 +1,96 @@
+use iledcolor_rs::config::{Inventory, Panel};
+use std::fs;
+
+const TOML: &str = r#"
+[[device]]
+alias = "rex"
+name = "iLedColor-1A2B"
+password = "123456"
+brightness = 3
+groups = ["pack"]
+
+[[device]]
+alias = "fido"
+id = "9E:19:3D:7C:21:BE"
+panel = { width = 64, height = 16 }
+groups = ["pack"]
+"#;
+
+const YAML: &str = r#"
+device:
+  - alias: rex
+    name: iLedColor-1A2B
+    password: "123456"
+    brightness: 3
+    groups: [pack]
+  - alias: fido
+    id: "9E:19:3D:7C:21:BE"
+    panel: { width: 64, height: 16 }
+    groups: [pack]
+"#;
+
+fn check(inventory: &Inventory) {
+    let rex = inventory.device("rex").unwrap();
+    assert_eq!(rex.target(), "iLedColor-1A2B");
+    assert!(rex.password().is_some());
+    assert_eq!(rex.brightness, Some(3));
+    assert_eq!(rex.panel, Panel::default());
+
+    let fido = inventory.device("fido").unwrap();
+    assert_eq!(fido.target(), "9E:19:3D:7C:21:BE");
+    assert_eq!(fido.panel, Panel { width: 64, height: 16 });
+
+    assert_eq!(inventory.group("pack").unwrap().len(), 2);
+    assert!(inventory.group("cats").is_err());
+    assert!(inventory.device("ghost").is_err());
+}
+
+#[test]
+fn parses_toml_and_yaml() {
+    check(&Inventory::parse(TOML).unwrap());
+    check(&Inventory::parse_yaml(YAML).unwrap());
+}
+
+#[test]
+fn loads_by_extension() {
+    let dir = std::env::temp_dir().join(format!("iledcolor-config-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for (file, text) in [("fleet.toml", TOML), ("fleet.yaml", YAML), ("fleet.yml", YAML)] {
+        let path = dir.join(file);
+        fs::write(&path, text).unwrap();
+        check(&Inventory::load(&path).unwrap());
+    }
+
+    let missing = Inventory::load(&dir.join("missing.toml")).unwrap_err().to_string();
+    assert!(missing.contains("missing.toml"), "{}", missing);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rejects_invalid_entries() {
+    let cases = [
+        ("alias = \"\"\nname = \"a\"", "alias can't be empty"),
+        ("alias = \"rex\"", "needs a name or an id"),
+        ("alias = \"rex\"\nname = \"a\"\npassword = \"12ab\"", "device rex"),
+        ("alias = \"rex\"\nname = \"a\"\nbrightness = 11", "brightness must be 0"),
+        ("alias = \"rex\"\nname = \"a\"\npanel = { width = 0, height = 12 }", "can't be zero"),
+        ("alias = \"rex\"\nname = \"a\"\nprofile = \"lamp\"", "unknown profile"),
+        ("alias = \"rex\"\nname = \"a\"\ncolour = \"red\"", "unknown field"),
+    ];
+    for (entry, error) in cases {
+        let text = format!("[[device]]\n{}\n", entry);
+        let message = Inventory::parse(&text).unwrap_err().to_string();
+        assert!(message.contains(error), "{:?} gave {:?}", entry, message);
+    }
+}
+
+#[test]
+fn rejects_clashing_names() {
+    let twice = "[[device]]\nalias = \"rex\"\nname = \"a\"\n[[device]]\nalias = \"rex\"\nname = \"b\"\n";
+    assert!(Inventory::parse(twice).unwrap_err().to_string().contains("used more than once"));
+
+    let group = "[[device]]\nalias = \"rex\"\nname = \"a\"\ngroups = [\"fido\"]\n[[device]]\nalias = \"fido\"\nname = \"b\"\n";
+    assert!(Inventory::parse(group).unwrap_err().to_string().contains("same name as a device alias"));
+
+    assert!(Inventory::parse_yaml("device:\n  - alias: rex\n").is_err());
+}
@@ -0,0 +1,84 @@
+use image::{Rgb, RgbImage, Rgba, RgbaImage};
+use iledcolor_rs::{
+    animation,
+    color::Color,
+    config::Panel,
+    correction::Correction,
+    fit::FitOptions,
+    image::ILedImage,
+    quantise::{Dither, Levels, Quantise},
+};
+
+const PANEL: Panel = Panel { width: 48, height: 12 };
+
+#[test]
+fn gamma_and_white_balance() {
+    let mut image = RgbImage::from_pixel(1, 1, Rgb([128, 255, 0]));
+    Correction { gamma: 2.2, gain: [1.0, 0.5, 1.0], max_power: None }.apply(&mut image);
+    // (128 / 255) ^ 2.2 is about 0.22
+    assert_eq!(image.get_pixel(0, 0).0, [56, 128, 0]);
+}
+
+#[test]
+fn power_limit_dims_bright_frames_only() {
+    let limit = Correction { max_power: Some(0.5), ..Correction::default() };
+    let mut white = RgbImage::from_pixel(4, 4, Rgb([255, 255, 255]));
+    limit.apply(&mut white);
+    assert_eq!(white.get_pixel(0, 0).0, [127, 127, 127]);
+
+    let mut dim = RgbImage::from_pixel(4, 4, Rgb([100, 0, 0]));
+    limit.apply(&mut dim);
+    assert_eq!(dim.get_pixel(0, 0).0, [100, 0, 0]);
+}
+
+#[test]
+fn corrects_gifs_that_could_otherwise_be_sent_as_they_are() {
+    let frames = vec![(RgbImage::from_pixel(48, 12, Rgb([200, 200, 200])), 10)];
+    let file = animation::encode(&frames, PANEL).unwrap();
+    let options = FitOptions { correction: Correction { gain: [0.5, 0.5, 0.5], ..Correction::default() }, ..FitOptions::default() };
+    let image = ILedImage::from_reader(&file[..], PANEL, &options).unwrap();
+    assert_ne!(image.data(), &file[..]);
+    assert_eq!(image.frames().unwrap()[0].0.get_pixel(0, 0).0, [100, 100, 100]);
+}
+
+fn grey(level: u8) -> RgbImage {
+    RgbImage::from_pixel(16, 16, Rgb([level; 3]))
+}
+
+#[test]
+fn rounds_to_the_nearest_level_without_dithering() {
+    let mut image = grey(100);
+    Quantise { levels: Levels::Bits(1), dither: Dither::None }.apply(&mut image);
+    assert!(image.pixels().all(|p| p.0 == [0, 0, 0]));
+}
+
+#[test]
+fn dithering_keeps_the_average() {
+    for dither in [Dither::FloydSteinberg, Dither::Bayer] {
+        let mut image = grey(128);
+        Quantise { levels: Levels::Bits(1), dither }.apply(&mut image);
+        assert!(image.pixels().all(|p| p.0 == [0, 0, 0] || p.0 == [255, 255, 255]));
+        let lit = image.pixels().filter(|p| p.0[0] == 255).count();
+        assert!((120..=136).contains(&lit), "{:?} lit {} of 256", dither, lit);
+    }
+}
+
+#[test]
+fn limits_images_to_a_palette() {
+    let mut image = RgbImage::from_fn(4, 1, |x, _| Rgb([[250, 10, 10], [20, 240, 30], [200, 0, 180], [5, 5, 5]][x as usize]));
+    let palette = vec![Color::new(255, 0, 0), Color::new(0, 255, 0), Color::new(0, 0, 0)];
+    Quantise { levels: Levels::Palette(palette), dither: Dither::None }.apply(&mut image);
+    let colours: Vec<_> = image.pixels().map(|p| p.0).collect();
+    assert_eq!(colours, [[255, 0, 0], [0, 255, 0], [255, 0, 0], [0, 0, 0]]);
+}
+
+#[test]
+fn flattens_transparency_onto_the_letterbox() {
+    let mut png = Vec::new();
+    RgbaImage::from_pixel(48, 12, Rgba([255, 255, 255, 0]))
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    let options = FitOptions { letterbox: Color::new(0, 0, 255), ..FitOptions::default() };
+    let image = ILedImage::from_reader(&png[..], PANEL, &options).unwrap();
+    assert_eq!(image.frames().unwrap()[0].0.get_pixel(10, 5).0, [0, 0, 255]);
+}
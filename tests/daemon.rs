@@ -0,0 +1,117 @@
+use iledcolor_rs::{
+    ble::Connector,
+    config::Inventory,
+    daemon::{self, Daemon},
+    packet::{Handle, Password},
+    sim::{SimDevice, Simulator},
+};
+use serde_json::{Value, json};
+use std::{sync::Arc, time::Duration};
+
+const INVENTORY: &str = r#"
+[[device]]
+alias = "rex"
+name = "iLedColor-1A2B"
+password = "123456"
+
+[[device]]
+alias = "ghost"
+name = "iLedColor-FFFF"
+"#;
+
+fn setup() -> (Daemon, SimDevice) {
+    let inventory = Inventory::parse(INVENTORY).unwrap();
+    let sim = Simulator::new();
+    let rex = sim.add("iLedColor-1A2B", Some("123456".parse::<Password>().unwrap()));
+    (Daemon::new(&inventory, Connector::Sim(sim)), rex)
+}
+
+async fn request(daemon: &Daemon, request: Value) -> Value {
+    serde_json::to_value(daemon.handle_line(&request.to_string()).await).unwrap()
+}
+
+#[tokio::test]
+async fn runs_json_requests() {
+    let (daemon, rex) = setup();
+    let ok = json!({"ok": true});
+    assert_eq!(request(&daemon, json!({"device": "rex", "command": "brightness", "level": 3})).await, ok);
+    assert_eq!(request(&daemon, json!({"device": "rex", "command": "enable", "on": false})).await, ok);
+    assert_eq!(request(&daemon, json!({"device": "rex", "command": "color", "rgb": [0, 255, 0]})).await, ok);
+
+    let state = rex.state();
+    assert_eq!(state.brightness, 3);
+    assert!(!state.enabled);
+    assert_eq!(state.uploads.len(), 1);
+
+    let list = request(&daemon, json!({"command": "list"})).await;
+    assert_eq!(list["devices"][1], json!({"alias": "rex", "connected": true, "queued": 0}));
+}
+
+#[tokio::test]
+async fn reports_bad_requests() {
+    let (daemon, _) = setup();
+    let error = async |line: &str| daemon.handle_line(line).await.error.unwrap();
+    assert!(error("not json").await.starts_with("invalid request"));
+    assert!(error(r#"{"command": "dance"}"#).await.starts_with("invalid request"));
+    assert_eq!(error(r#"{"command": "brightness", "level": 3}"#).await, "missing device");
+    assert_eq!(error(r#"{"device": "spot", "command": "brightness", "level": 3}"#).await, "no device with alias spot");
+    assert!(error(r#"{"device": "rex", "command": "brightness", "level": 11}"#).await.contains("0 (brightest) to 10"));
+    assert!(error(r#"{"device": "ghost", "command": "brightness", "level": 3}"#).await.contains("not found"));
+    assert!(error(r#"{"device": "rex", "command": "password"}"#).await.contains("needs old, new or both"));
+}
+
+#[tokio::test]
+async fn reconnects_with_a_changed_password() {
+    let (daemon, rex) = setup();
+    let change = json!({"device": "rex", "command": "password", "old": "123456", "new": "654321"});
+    assert_eq!(request(&daemon, change).await, json!({"ok": true}));
+
+    // A failed reconnect drops the session, the next command connects from scratch.
+    rex.set_out_of_range(true);
+    let brightness = json!({"device": "rex", "command": "brightness", "level": 5});
+    assert_eq!(request(&daemon, brightness.clone()).await["ok"], false);
+    rex.set_out_of_range(false);
+    assert_eq!(request(&daemon, brightness).await, json!({"ok": true}));
+    assert_eq!(rex.state().brightness, 5);
+}
+
+#[tokio::test(start_paused = true)]
+async fn keepalive_notices_a_silent_device() {
+    let (daemon, rex) = setup();
+    let brightness = json!({"device": "rex", "command": "brightness", "level": 3});
+    assert_eq!(request(&daemon, brightness).await, json!({"ok": true}));
+    let connected = async || request(&daemon, json!({"command": "list"})).await["devices"][1]["connected"].clone();
+
+    // The link stays up but the device stops answering, so the keepalive at 10s gives up on the session.
+    rex.ignore_replies(Handle::TestPass, 1);
+    tokio::time::sleep(Duration::from_secs(16)).await;
+    assert_eq!(connected().await, false);
+    // The next one connects again.
+    tokio::time::sleep(Duration::from_secs(5)).await;
+    assert_eq!(connected().await, true);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn serves_the_socket_once() {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let path = std::env::temp_dir().join(format!("iledd-test-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    // A socket nobody listens on any more is replaced.
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    let listener = daemon::bind(&path).unwrap();
+    assert!(daemon::bind(&path).is_err());
+
+    let (daemon, _) = setup();
+    tokio::spawn(Arc::new(daemon).serve(listener));
+    let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+    let (read, mut write) = stream.into_split();
+    write.write_all(b"{\"command\": \"list\"}\n\nnope\n").await.unwrap();
+    let mut lines = BufReader::new(read).lines();
+    let list: Value = serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+    assert_eq!(list["ok"], true);
+    let error: Value = serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+    assert_eq!(error["ok"], false);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,62 @@
+use image::{Rgb, RgbImage};
+use iledcolor_rs::{
+    color::Color,
+    config::Panel,
+    fit::{self, Filter, Fit, FitOptions},
+};
+
+const PANEL: Panel = Panel { width: 6, height: 4 };
+
+// One string per row, r and b are red and blue pixels and . is the letterbox.
+fn grid(rows: &[&str]) -> RgbImage {
+    RgbImage::from_fn(rows[0].len() as u32, rows.len() as u32, |x, y| {
+        Rgb(match rows[y as usize].as_bytes()[x as usize] {
+            b'r' => [255, 0, 0],
+            b'b' => [0, 0, 255],
+            _ => [0, 0, 0],
+        })
+    })
+}
+
+fn fitted(source: &[&str], fit: Fit) -> RgbImage {
+    let options = FitOptions { fit, filter: Filter::Nearest, letterbox: Color::BLACK, ..FitOptions::default() };
+    fit::fit(&grid(source), PANEL, &options)
+}
+
+#[test]
+fn stretch_ignores_the_aspect_ratio() {
+    let expected = ["rrrbbb", "rrrbbb", "rrrbbb", "rrrbbb"];
+    assert_eq!(fitted(&["rb"], Fit::Stretch), grid(&expected));
+}
+
+#[test]
+fn contain_letterboxes() {
+    let wide = ["rrrbbb", "rrrbbb", "rrrbbb", "......"];
+    assert_eq!(fitted(&["rb"], Fit::Contain), grid(&wide));
+    let tall = ["..rr..", "..rr..", "..bb..", "..bb.."];
+    assert_eq!(fitted(&["r", "b"], Fit::Contain), grid(&tall));
+}
+
+#[test]
+fn cover_crops_both_sides() {
+    let tall = ["rrrrrr", "rrrrrr", "bbbbbb", "bbbbbb"];
+    assert_eq!(fitted(&["r", "b"], Fit::Cover), grid(&tall));
+    let wide = ["rrbb", "rrbb"];
+    let expected = ["rrrbbb", "rrrbbb", "rrrbbb", "rrrbbb"];
+    assert_eq!(fitted(&wide, Fit::Cover), grid(&expected));
+}
+
+#[test]
+fn center_keeps_the_size() {
+    let small = ["......", "..rb..", "......", "......"];
+    assert_eq!(fitted(&["rb"], Fit::Center), grid(&small));
+    let large = ["rrrrbbbb", "rrrrbbbb"];
+    let cropped = ["......", "rrrbbb", "rrrbbb", "......"];
+    assert_eq!(fitted(&large, Fit::Center), grid(&cropped));
+}
+
+#[test]
+fn tile_repeats_from_the_corner() {
+    let expected = ["rbbrbb", "bbrbbr", "rbbrbb", "bbrbbr"];
+    assert_eq!(fitted(&["rbb", "bbr"], Fit::Tile), grid(&expected));
+}
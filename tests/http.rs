@@ -0,0 +1,189 @@
+#![cfg(feature = "http")]
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode, header},
+};
+use http_body_util::BodyExt;
+use iledcolor_rs::{
+    ble::Connector,
+    config::Inventory,
+    daemon::Daemon,
+    http,
+    packet::{Handle, Password},
+    sim::{SimDevice, Simulator},
+};
+use serde_json::{Value, json};
+use std::{io::Cursor, sync::Arc, time::Duration};
+use tower::ServiceExt;
+
+const INVENTORY: &str = r#"
+[[device]]
+alias = "rex"
+name = "iLedColor-1A2B"
+password = "123456"
+
+[[device]]
+alias = "fido"
+id = "9E:19:3D:7C:21:BE"
+
+[[device]]
+alias = "ghost"
+name = "iLedColor-FFFF"
+"#;
+
+fn setup() -> (Router, SimDevice, SimDevice) {
+    let inventory = Inventory::parse(INVENTORY).unwrap();
+    let sim = Simulator::new();
+    let rex = sim.add("iLedColor-1A2B", Some("123456".parse::<Password>().unwrap()));
+    let fido = sim.add("9E:19:3D:7C:21:BE", None);
+    let daemon = Arc::new(Daemon::new(&inventory, Connector::Sim(sim)));
+    (http::router(daemon), rex, fido)
+}
+
+async fn send(router: &Router, request: Request<Body>) -> (StatusCode, Value) {
+    let response = router.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+fn put(uri: &str, body: Value) -> Request<Body> {
+    Request::put(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn get(uri: &str) -> Request<Body> {
+    Request::get(uri).body(Body::empty()).unwrap()
+}
+
+fn multipart(uri: &str, field: &str, file: &[u8]) -> Request<Body> {
+    let boundary = "iledcolor-boundary";
+    let mut body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"{field}\"; filename=\"design.png\"\r\nContent-Type: image/png\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend(file);
+    body.extend(format!("\r\n--{boundary}--\r\n").as_bytes());
+    Request::post(uri)
+        .header(header::CONTENT_TYPE, format!("multipart/form-data; boundary={boundary}"))
+        .body(Body::from(body))
+        .unwrap()
+}
+
+// Pixels follow the 24 byte stream header and the 22 byte image header.
+const PIXELS: usize = 46;
+
+fn png() -> Vec<u8> {
+    let image = image::RgbImage::from_pixel(48, 12, image::Rgb([0, 128, 255]));
+    let mut bytes = Cursor::new(Vec::new());
+    image.write_to(&mut bytes, image::ImageFormat::Png).unwrap();
+    bytes.into_inner()
+}
+
+#[tokio::test]
+async fn lists_devices() {
+    let (router, _, _) = setup();
+    let (status, body) = send(&router, get("/devices")).await;
+    assert_eq!(status, StatusCode::OK);
+    let aliases: Vec<_> = body.as_array().unwrap().iter().map(|d| d["alias"].as_str().unwrap()).collect();
+    assert_eq!(aliases, ["fido", "ghost", "rex"]);
+}
+
+#[tokio::test]
+async fn sets_brightness_enable_and_color() {
+    let (router, rex, _) = setup();
+    let (status, body) = send(&router, put("/devices/rex/brightness", json!({"level": 3}))).await;
+    assert_eq!((status, body), (StatusCode::OK, json!({"ok": true})));
+    let (status, _) = send(&router, put("/devices/rex/enable", json!({"on": false}))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&router, put("/devices/rex/color", json!({"rgb": [255, 0, 0]}))).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let state = rex.state();
+    assert_eq!(state.brightness, 3);
+    assert!(!state.enabled);
+    assert_eq!(state.uploads.len(), 1);
+    assert_eq!(&state.uploads[0][PIXELS..PIXELS + 3], &[255, 0, 0]);
+}
+
+#[tokio::test]
+async fn uploads_image_and_reports_status() {
+    let (router, _, fido) = setup();
+    let (status, body) = send(&router, multipart("/devices/fido/image", "image", &png())).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(body["state"], "queued");
+    let uri = format!("/uploads/{}", body["id"]);
+
+    let mut body = Value::Null;
+    for _ in 0..100 {
+        (_, body) = send(&router, get(&uri)).await;
+        if body["state"] != "queued" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(body["state"], "done", "{}", body);
+    assert_eq!(fido.state().uploads.len(), 1);
+    assert_eq!(&fido.state().uploads[0][PIXELS..PIXELS + 3], &[0, 128, 255]);
+}
+
+#[tokio::test]
+async fn forgets_old_uploads() {
+    let (router, _, _) = setup();
+    let mut last = Value::Null;
+    for _ in 0..=http::MAX_UPLOADS {
+        (_, last) = send(&router, multipart("/devices/fido/image", "image", &png())).await;
+    }
+    let uri = format!("/uploads/{}", last["id"]);
+    for _ in 0..100 {
+        let (_, body) = send(&router, get(&uri)).await;
+        if body["state"] != "queued" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let (status, _) = send(&router, get("/uploads/1")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(&router, get(&uri)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn reports_failed_commands() {
+    let (router, _, _) = setup();
+    let (status, _) = send(&router, put("/devices/spot/brightness", json!({"level": 3}))).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, body) = send(&router, put("/devices/ghost/brightness", json!({"level": 3}))).await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(body["ok"], false);
+    let (status, body) = send(&router, put("/devices/rex/brightness", json!({"level": 11}))).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(body["error"].as_str().unwrap().contains("0 (brightest) to 10"));
+    let (status, _) = send(&router, get("/uploads/42")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, body) = send(&router, multipart("/devices/fido/image", "image", b"not an image")).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["ok"], false);
+    let (status, _) = send(&router, multipart("/devices/fido/image", "file", &png())).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test(start_paused = true)]
+async fn times_out_silent_devices() {
+    let (router, rex, _) = setup();
+    rex.ignore_replies(Handle::Brightness, 1);
+    let (status, body) = send(&router, put("/devices/rex/brightness", json!({"level": 3}))).await;
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(body["ok"], false);
+}
+
+#[tokio::test]
+async fn serves_openapi() {
+    let (router, _, _) = setup();
+    let (status, body) = send(&router, get("/openapi.json")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["paths"]["/devices/{alias}/image"]["post"].is_object());
+}
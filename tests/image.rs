@@ -0,0 +1,143 @@
+use iledcolor_rs::{
+    color::Color,
+    config::Panel,
+    correction::Correction,
+    fit::FitOptions,
+    image::{Effect, ILedImage, ImageMetadata, Mode},
+    packet::CtnData,
+    playlist::Playlist,
+    text::{self, MarqueeOptions, TextStyle, Typeface},
+};
+use std::time::Duration;
+
+// The two headers captured from the official app.
+const STILL: [u8; 22] = [0, 0, 0, 0, 0, 0x30, 0, 0x0c, 0, 0, 0, 1, 0, 1, 0, 1, 0, 0x32, 0, 0x64, 0, 0];
+const GIF: [u8; 22] = [0, 0, 0, 0, 0, 0x30, 0, 0x0c, 0, 0, 0, 6, 0, 1, 0, 0x64, 0x04, 0, 0, 0x64, 0, 0];
+
+#[test]
+fn headers_match_the_captures() {
+    assert_eq!(ImageMetadata::new(Mode::Still).to_bytes(), STILL);
+    assert_eq!(ImageMetadata::new(Mode::Animation).to_bytes(), GIF);
+}
+
+#[test]
+fn builder_sets_fields() {
+    let metadata = ImageMetadata::new(Mode::Still).size(64, 16).effect(Effect::Blink).hold(20).speed(0x0203);
+    let bytes = metadata.to_bytes();
+    assert_eq!(&bytes[4..8], &[0, 64, 0, 16]);
+    assert_eq!(&bytes[12..20], &[0, 6, 0, 1, 0, 20, 2, 3]);
+    assert_eq!((metadata.width(), metadata.height(), metadata.current_effect()), (64, 16, Effect::Blink));
+    assert_eq!((metadata.field(9), metadata.field(10), metadata.field(11)), (Some(0x0203), Some(0), None));
+    assert_eq!(metadata.to_string(), "still 64x16, effect blink, fields 0:0000 1:0000 4:0000 7:0001 8:0014 9:0203 10:0000");
+}
+
+#[test]
+fn effects_go_in_the_header() {
+    let image = ILedImage::solid_color(Panel::default(), Color::BLACK, &FitOptions::default()).with_effect(Effect::ScrollLeft, Some(5));
+    let bytes = image.metadata().to_bytes();
+    assert_eq!((&bytes[12..14], &bytes[18..20]), (&[0, 2][..], &[0, 5][..]));
+    assert!(!image.metadata().is_captured());
+
+    let plain = ILedImage::solid_color(Panel { width: 64, height: 16 }, Color::BLACK, &FitOptions::default()).with_effect(Effect::Static, None);
+    assert!(plain.metadata().is_captured());
+}
+
+#[test]
+fn drawn_images_are_corrected() {
+    let fit = FitOptions { correction: Correction { max_power: Some(0.5), ..Correction::default() }, ..FitOptions::default() };
+    let white = ILedImage::solid_color(Panel::default(), Color::WHITE, &fit);
+    assert!(white.data().iter().all(|&value| value == 127));
+
+    let style = TextStyle { foreground: Color::WHITE, ..TextStyle::default() };
+    let text = text::render("Rex", &Typeface::Pixel, Panel::default(), &style, &FitOptions::default());
+    let fit = FitOptions { correction: Correction { gain: [1.0, 0.0, 0.0], ..Correction::default() }, ..fit };
+    let red = text::render("Rex", &Typeface::Pixel, Panel::default(), &style, &fit);
+    assert!(text.data().contains(&255) && red.data().chunks(3).all(|pixel| pixel[1..] == [0, 0]));
+}
+
+#[test]
+fn unknown_codes_read_back() {
+    let mut bytes = STILL.to_vec();
+    bytes[11] = 0x09;
+    bytes[13] = 0x42;
+    bytes.extend([1, 2, 3]);
+    let (image, len) = ILedImage::read(&bytes).unwrap();
+    assert_eq!(len, bytes.len());
+    let metadata = image.metadata();
+    assert_eq!((metadata.mode(), metadata.current_effect()), (Mode::Other(9), Effect::Other(0x42)));
+    assert_eq!(metadata.to_bytes(), bytes[..22]);
+    assert!(!metadata.is_captured());
+    assert!(metadata.to_string().starts_with("mode 0x0009 48x12, effect 0x0042, fields"));
+    assert!(image.frames().is_err());
+
+    assert_eq!(Effect::from_code(0x0002), Effect::ScrollLeft);
+    assert_eq!(Effect::ScrollLeft.to_string(), "scroll-left");
+}
+
+#[test]
+fn playlists_put_programs_back_to_back() {
+    let panel = Panel { width: 2, height: 1 };
+    let mut playlist = Playlist::new();
+    playlist.push(ILedImage::solid_color(panel, Color::new(255, 0, 0), &FitOptions::default()), Duration::from_secs(5)).unwrap();
+    playlist.push(ILedImage::solid_color(panel, Color::new(0, 0, 255), &FitOptions::default()), Duration::from_millis(1500)).unwrap();
+    let bytes = playlist.to_ctn_data().to_bytes();
+
+    // crc32, then the program count where a single upload has 01
+    assert_eq!(bytes[4], 2);
+    let programs: Vec<_> = bytes[24..].chunks(ImageMetadata::LEN + 6).collect();
+    assert_eq!(programs.len(), 2);
+    // hold in tenths of a second
+    assert_eq!(&programs[0][16..18], &[0, 50]);
+    assert_eq!(&programs[1][16..18], &[0, 15]);
+    assert_eq!(&programs[1][ImageMetadata::LEN..], &[0, 0, 255, 0, 0, 255]);
+    assert!(playlist.push(ILedImage::solid_color(panel, Color::new(0, 0, 0), &FitOptions::default()), Duration::from_secs(7000)).is_err());
+}
+
+#[test]
+fn images_read_back() {
+    let panel = Panel { width: 4, height: 2 };
+    let still = ILedImage::solid_color(panel, Color::new(10, 20, 30), &FitOptions::default()).with_effect(Effect::Blink, Some(3));
+    let decoded = ILedImage::from_bytes(&still.to_bytes()).unwrap();
+    assert_eq!(decoded.metadata(), still.metadata());
+    let frames = decoded.frames().unwrap();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0].0.get_pixel(3, 1).0, [10, 20, 30]);
+
+    // a byte short or over
+    let bytes = still.to_bytes();
+    assert!(ILedImage::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(ILedImage::from_bytes(&[&bytes[..], &[0]].concat()).is_err());
+}
+
+#[test]
+fn uploads_read_back_into_programs() {
+    let panel = Panel { width: 48, height: 12 };
+    let marquee = text::marquee("Rex", &Typeface::Pixel, panel, &TextStyle::default(), &MarqueeOptions::default(), &FitOptions::default()).unwrap();
+    let mut playlist = Playlist::new();
+    playlist.push(marquee, Duration::from_secs(10)).unwrap();
+    playlist.push(ILedImage::solid_color(panel, Color::new(0, 255, 0), &FitOptions::default()), Duration::from_secs(2)).unwrap();
+    let bytes = playlist.to_ctn_data().to_bytes();
+
+    let ctn = CtnData::from_bytes(&bytes).unwrap();
+    let decoded = Playlist::from_ctn_data(&ctn).unwrap();
+    assert_eq!(decoded.len(), 2);
+    assert_eq!(decoded.programs()[0].metadata().mode(), Mode::Animation);
+    assert!(decoded.programs()[0].frames().unwrap().len() > 1);
+    assert_eq!(decoded.programs()[1].frames().unwrap()[0].0.get_pixel(0, 0).0, [0, 255, 0]);
+
+    let mut corrupt = bytes.clone();
+    *corrupt.last_mut().unwrap() ^= 1;
+    assert!(CtnData::from_bytes(&corrupt).is_err());
+}
+
+#[test]
+fn reader_errors_are_returned() {
+    struct Broken;
+    impl std::io::Read for Broken {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("unplugged"))
+        }
+    }
+    let result = ILedImage::from_reader(Broken, Panel::default(), &Default::default());
+    assert!(matches!(result, Err(image::ImageError::IoError(_))));
+}
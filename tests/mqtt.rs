@@ -0,0 +1,147 @@
+#![cfg(feature = "mqtt")]
+use iledcolor_rs::{
+    ble::Connector,
+    command::Command,
+    config::{Inventory, Panel},
+    daemon::Daemon,
+    mqtt::{self, BridgeOptions, Message, brightness_level},
+    packet::Password,
+    sim::Simulator,
+};
+use serde_json::{Value, json};
+use std::{fs, path::PathBuf};
+
+const PANEL: Panel = Panel { width: 48, height: 12 };
+
+fn image_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("iledcolor-mqtt-{}-{}", name, std::process::id()));
+    fs::create_dir_all(dir.join("designs")).unwrap();
+    image::RgbImage::new(48, 12).save(dir.join("designs/design.png")).unwrap();
+    image::RgbImage::new(48, 12).save(dir.join("secret.png")).unwrap();
+    dir
+}
+
+async fn command(setting: &str, payload: &str, options: &BridgeOptions) -> Result<Command, String> {
+    mqtt::command(setting, payload, PANEL, options).await
+}
+
+#[test]
+fn brightness_counts_up() {
+    assert_eq!(brightness_level("0"), Ok(10));
+    assert_eq!(brightness_level("10"), Ok(0));
+    assert_eq!(brightness_level("3"), Ok(7));
+    for bad in ["11", "-1", "bright", ""] {
+        assert!(brightness_level(bad).is_err(), "{}", bad);
+    }
+}
+
+#[tokio::test]
+async fn parses_commands() {
+    let options = BridgeOptions::default();
+    assert!(matches!(command("power", "ON", &options).await, Ok(Command::Enable { on: true })));
+    assert!(matches!(command("power", "OFF", &options).await, Ok(Command::Enable { on: false })));
+    assert!(command("power", "on", &options).await.is_err());
+    assert!(matches!(command("brightness", "10", &options).await, Ok(Command::Brightness { level: 0 })));
+    assert!(matches!(command("rgb", "255,0,0", &options).await, Ok(Command::Color { rgb: [255, 0, 0] })));
+    assert!(matches!(command("rgb", "#00ff00", &options).await, Ok(Command::Color { rgb: [0, 255, 0] })));
+    assert!(command("rgb", "nope", &options).await.is_err());
+    assert_eq!(command("volume", "3", &options).await.unwrap_err(), "unknown setting volume");
+}
+
+#[tokio::test]
+async fn images_stay_in_the_image_directory() {
+    let dir = image_dir("images");
+    let off = BridgeOptions::default();
+    assert!(command("image", "designs/design.png", &off).await.unwrap_err().contains("turned off"));
+    assert!(command("image", "https://example.com/design.png", &off).await.unwrap_err().contains("turned off"));
+
+    let options = BridgeOptions { image_dir: Some(dir.join("designs")), ..BridgeOptions::default() };
+    assert!(matches!(command("image", "design.png", &options).await, Ok(Command::Upload(_))));
+    assert!(matches!(command("image", "file://design.png", &options).await, Ok(Command::Upload(_))));
+    assert!(command("image", "../secret.png", &options).await.unwrap_err().contains("outside"));
+    let absolute = dir.join("secret.png").display().to_string();
+    assert!(command("image", &absolute, &options).await.unwrap_err().contains("outside"));
+    assert!(command("image", "missing.png", &options).await.is_err());
+
+    let large = fs::File::create(dir.join("designs/large.png")).unwrap();
+    large.set_len(mqtt::MAX_IMAGE_BYTES + 1).unwrap();
+    assert!(command("image", "large.png", &options).await.unwrap_err().contains("larger than"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reports_state() {
+    assert_eq!(mqtt::state(&Command::Enable { on: true }), Some(("power", "ON".to_string())));
+    assert_eq!(mqtt::state(&Command::Enable { on: false }), Some(("power", "OFF".to_string())));
+    assert_eq!(mqtt::state(&Command::Brightness { level: 7 }), Some(("brightness", "3".to_string())));
+    assert_eq!(mqtt::state(&Command::Color { rgb: [1, 2, 3] }), Some(("rgb", "1,2,3".to_string())));
+    assert_eq!(mqtt::state(&Command::List), None);
+}
+
+#[test]
+fn routes_set_topics() {
+    let options = BridgeOptions::default();
+    assert_eq!(mqtt::parse_topic(&options, "iledcolor/rex/brightness/set"), Some(("rex", "brightness")));
+    for other in ["iledcolor/rex/brightness", "iledcolor/rex/brightness/set/more", "iledcolorx/rex/power/set", "other/rex/power/set"] {
+        assert_eq!(mqtt::parse_topic(&options, other), None, "{}", other);
+    }
+}
+
+#[tokio::test]
+async fn set_topics_reach_the_daemon() {
+    let inventory = Inventory::parse("[[device]]\nalias = \"rex\"\nname = \"iLedColor-1A2B\"\npassword = \"123456\"\n").unwrap();
+    let sim = Simulator::new();
+    let rex = sim.add("iLedColor-1A2B", Some("123456".parse::<Password>().unwrap()));
+    let daemon = Daemon::new(&inventory, Connector::Sim(sim));
+    let options = BridgeOptions::default();
+    let message = |topic: &str, retain, payload: &str| Message { topic: topic.to_string(), retain, payload: payload.to_string() };
+
+    let sent = mqtt::handle(&daemon, &options, "rex", "brightness", "3").await;
+    assert_eq!(sent, vec![
+        message("iledcolor/rex/brightness", true, "3"),
+        message("iledcolor/rex/result", false, r#"{"ok":true}"#),
+    ]);
+    assert_eq!(rex.state().brightness, 7);
+
+    let sent = mqtt::handle(&daemon, &options, "rex", "power", "OFF").await;
+    assert_eq!(sent[0], message("iledcolor/rex/power", true, "OFF"));
+    assert!(!rex.state().enabled);
+
+    // A payload that doesn't parse changes no state and only answers on the result topic.
+    let sent = mqtt::handle(&daemon, &options, "rex", "brightness", "11").await;
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].topic, "iledcolor/rex/result");
+    assert!(!sent[0].retain);
+    assert_eq!(serde_json::from_str::<Value>(&sent[0].payload).unwrap()["ok"], false);
+    assert_eq!(rex.state().brightness, 7);
+}
+
+#[tokio::test]
+async fn announces_discovery_configs() {
+    let inventory = Inventory::parse("[[device]]\nalias = \"rex\"\nname = \"iLedColor-1A2B\"\n").unwrap();
+    let daemon = Daemon::new(&inventory, Connector::Sim(Simulator::new()));
+    let sent = mqtt::discovery(&BridgeOptions::default(), &daemon.status());
+    assert!(sent.iter().all(|m| m.retain));
+    let topics: Vec<&str> = sent.iter().map(|m| m.topic.as_str()).collect();
+    assert_eq!(topics, [
+        "iledcolor/status",
+        "homeassistant/light/iledcolor_rex/config",
+        "homeassistant/text/iledcolor_rex_image/config",
+    ]);
+    assert_eq!(sent[0].payload, "online");
+
+    let light: Value = serde_json::from_str(&sent[1].payload).unwrap();
+    assert_eq!(light["unique_id"], "iledcolor_rex");
+    assert_eq!(light["command_topic"], "iledcolor/rex/power/set");
+    assert_eq!(light["state_topic"], "iledcolor/rex/power");
+    assert_eq!(light["brightness_command_topic"], "iledcolor/rex/brightness/set");
+    assert_eq!(light["brightness_scale"], 10);
+    assert_eq!(light["rgb_command_topic"], "iledcolor/rex/rgb/set");
+    assert_eq!(light["availability"], json!([{ "topic": "iledcolor/status" }, { "topic": "iledcolor/rex/available" }]));
+    assert_eq!(light["device"]["identifiers"], json!(["iledcolor_rex"]));
+
+    let image: Value = serde_json::from_str(&sent[2].payload).unwrap();
+    assert_eq!(image["unique_id"], "iledcolor_rex_image");
+    assert_eq!(image["command_topic"], "iledcolor/rex/image/set");
+    assert_eq!(image["device"], light["device"]);
+}
@@ -0,0 +1,13 @@
+use image::{Rgb, RgbImage};
+use iledcolor_rs::preview;
+
+#[test]
+fn two_rows_per_line() {
+    // red over blue, then a lone green row
+    let image = RgbImage::from_fn(1, 3, |_, y| Rgb([[255, 0, 0], [0, 0, 255], [0, 255, 0]][y as usize]));
+    assert_eq!(preview::lines(&image), 2);
+    assert_eq!(
+        preview::half_blocks(&image),
+        "\x1b[38;2;255;0;0m\x1b[48;2;0;0;255m▀\x1b[0m\n\x1b[38;2;0;255;0m\x1b[49m▀\x1b[0m\n"
+    );
+}
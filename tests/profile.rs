@@ -0,0 +1,64 @@
+use iledcolor_rs::{
+    config::{Inventory, Panel},
+    fit::FitOptions,
+    image::{ILedImage, Mode},
+    packet::CtnData,
+    profile::{self, PanelProfile},
+};
+
+#[test]
+fn parses_names_and_sizes() {
+    assert_eq!("collar".parse::<PanelProfile>().unwrap(), profile::COLLAR);
+    assert_eq!("48x12".parse::<PanelProfile>().unwrap(), profile::COLLAR);
+
+    let wide: PanelProfile = "64x16".parse().unwrap();
+    assert_eq!(wide.name, "custom");
+    assert_eq!(wide.panel, Panel { width: 64, height: 16 });
+    assert_eq!(wide.to_string(), "custom 64x16");
+
+    assert!("lamp".parse::<PanelProfile>().is_err());
+}
+
+#[test]
+fn detects_from_advertised_name() {
+    assert_eq!(PanelProfile::detect("iLedColor-1A2B", None), Some(profile::COLLAR));
+    assert_eq!(PanelProfile::detect("iLedColor-1A2B", Some([0, 0])), Some(profile::COLLAR));
+    assert_eq!(PanelProfile::detect("iLedColor-1A2B", Some([0, 1])), None);
+    assert_eq!(PanelProfile::detect("Speaker", None), None);
+}
+
+#[test]
+fn check_rejects_what_the_panel_cannot_take() {
+    let still = ILedImage::solid_color(profile::COLLAR.panel, "red".parse().unwrap(), &FitOptions::default());
+    let data = CtnData::new(still.to_bytes());
+    assert!(profile::COLLAR.check(&data).is_ok());
+
+    let small = PanelProfile { max_payload: 100, ..profile::COLLAR };
+    assert!(small.check(&data).is_err());
+
+    let gif_only = PanelProfile { modes: &[Mode::Animation], ..profile::COLLAR };
+    assert!(gif_only.check(&data).is_err());
+}
+
+#[test]
+fn inventory_profiles() {
+    let inventory = Inventory::parse(
+        r#"
+[[device]]
+alias = "rex"
+name = "iLedColor-1A2B"
+profile = "collar"
+
+[[device]]
+alias = "sign"
+name = "iLedColor-9F00"
+panel = { width = 64, height = 16 }
+"#,
+    )
+    .unwrap();
+    assert_eq!(inventory.device("rex").unwrap().profile(), profile::COLLAR);
+    assert_eq!(inventory.device("sign").unwrap().profile().panel, Panel { width: 64, height: 16 });
+
+    assert!(Inventory::parse("[[device]]\nalias = \"x\"\nname = \"a\"\nprofile = \"lamp\"\n").is_err());
+    assert!(Inventory::parse("[[device]]\nalias = \"x\"\nname = \"a\"\nprofile = \"collar\"\npanel = { width = 64, height = 16 }\n").is_err());
+}
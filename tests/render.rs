@@ -0,0 +1,61 @@
+// The render subcommand end to end, it writes what would be uploaded without touching bluetooth.
+use iledcolor_rs::{animation, config::Panel};
+use std::{path::PathBuf, process::Command};
+
+fn render(out: &str, args: &[&str]) -> PathBuf {
+    let out = std::env::temp_dir().join(format!("iledcolor-render-{}-{}", std::process::id(), out));
+    let status = Command::new(env!("CARGO_BIN_EXE_iledcolor-rs"))
+        .arg("render")
+        .arg("--out")
+        .arg(&out)
+        .args(args)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    out
+}
+
+#[test]
+fn renders_text_like_the_golden_image() {
+    let out = render("rex.png", &["text", "Rex"]);
+    let rendered = image::open(&out).unwrap().to_rgb8();
+    std::fs::remove_file(&out).unwrap();
+    assert!(rendered == image::open("tests/golden/pixel-rex.png").unwrap().to_rgb8());
+}
+
+#[test]
+fn renders_animations_as_the_gif_sent() {
+    let out = render("marquee.gif", &["marquee", "Rex"]);
+    let gif = std::fs::read(&out).unwrap();
+    std::fs::remove_file(&out).unwrap();
+    assert!(animation::conforms(&gif, Panel { width: 48, height: 12 }));
+}
+
+#[test]
+fn corrects_drawn_colours() {
+    let out = render("white.png", &["color", "white", "--max-power", "0.5"]);
+    let rendered = image::open(&out).unwrap().to_rgb8();
+    std::fs::remove_file(&out).unwrap();
+    assert!(rendered.pixels().all(|pixel| pixel.0 == [127; 3]));
+}
+
+#[test]
+fn refuses_commands_that_draw_nothing() {
+    let output = Command::new(env!("CARGO_BIN_EXE_iledcolor-rs"))
+        .args(["render", "--out", "unused.png", "brightness", "3"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn reports_failures_as_json() {
+    let output = Command::new(env!("CARGO_BIN_EXE_iledcolor-rs"))
+        .args(["render", "--out", "unused.png", "--json", "send", "/nonexistent/design.png"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let response: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(response["ok"], false);
+    assert!(response["error"].as_str().unwrap().contains("/nonexistent/design.png"));
+}
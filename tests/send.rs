@@ -0,0 +1,112 @@
+use iledcolor_rs::{
+    ble::{ConnectOptions, Connector, Link},
+    error::Error,
+    packet::{CtnData, Handle, Notification, NotificationType, Packet},
+    profile::{self, PanelProfile},
+    send::Session,
+    sim::{SimDevice, Simulator},
+};
+use std::time::Duration;
+
+const ID: &str = "iLedColor-1A2B";
+
+async fn open(retries: u32) -> (Session, SimDevice) {
+    let sim = Simulator::new();
+    let rex = sim.add(ID, None);
+    let options = ConnectOptions {
+        connect_retries: retries,
+        backoff: Duration::from_millis(1),
+        response_timeout: Duration::from_millis(50),
+        ..ConnectOptions::default()
+    };
+    let link = Link::Sim(sim.open(ID, options).unwrap());
+    (Session::open(link, None).await.unwrap(), rex)
+}
+
+// Seven chunks of 492 bytes, with bytes that differ from chunk to chunk.
+fn upload() -> CtnData {
+    CtnData::new((0..3000).map(|i| (i % 251) as u8).collect())
+}
+
+#[tokio::test]
+async fn uploads_are_checked_against_the_profile() {
+    let sim = Simulator::new();
+    let rex = sim.add(ID, None);
+    let link = Connector::Sim(sim).connect(ID).await.unwrap();
+    let mut session = Session::open(link, None).await.unwrap();
+    assert_eq!(session.connect_reply(), profile::COLLAR.connect_reply);
+
+    let oversized = CtnData::new(vec![0; profile::MAX_PAYLOAD]);
+    assert!(matches!(session.upload(&oversized).await, Err(Error::TooLarge(_, profile::MAX_PAYLOAD))));
+    // Whatever a profile claims, the stream can't announce more than 16 bits of length.
+    session.set_profile(PanelProfile { max_payload: usize::MAX, ..profile::COLLAR });
+    assert!(matches!(session.upload(&oversized).await, Err(Error::TooLarge(_, 65535))));
+
+    let small = CtnData::new(vec![0; 100]);
+    session.set_profile(PanelProfile { max_payload: 64, ..profile::COLLAR });
+    assert!(matches!(session.upload(&small).await, Err(Error::TooLarge(124, 64))));
+    assert!(rex.state().uploads.is_empty());
+
+    session.set_profile(profile::COLLAR);
+    session.upload(&small).await.unwrap();
+    assert_eq!(rex.state().uploads.len(), 1);
+}
+
+#[tokio::test]
+async fn resumes_a_dropped_upload_from_the_start() {
+    let (mut session, rex) = open(3).await;
+    rex.drop_after_chunks(3);
+    session.upload(&upload()).await.unwrap();
+    let state = rex.state();
+    assert_eq!(state.streams_started, 2);
+    assert_eq!(state.uploads, vec![upload().to_bytes()]);
+}
+
+#[tokio::test]
+async fn resumes_when_a_reply_goes_missing() {
+    let (mut session, rex) = open(3).await;
+    rex.ignore_replies(Handle::Continue, 1);
+    session.upload(&upload()).await.unwrap();
+    let state = rex.state();
+    assert_eq!(state.streams_started, 2);
+    assert_eq!(state.uploads, vec![upload().to_bytes()]);
+}
+
+#[tokio::test]
+async fn gives_up_after_the_retries() {
+    let (mut session, rex) = open(2).await;
+    rex.ignore_replies(Handle::EndStream, 10);
+    assert!(matches!(session.upload(&upload()).await, Err(Error::NoResponse)));
+    let state = rex.state();
+    assert_eq!(state.streams_started, 3);
+    // Only the acknowledgements went missing, so the device took every attempt.
+    assert_eq!(state.uploads.len(), 3);
+}
+
+#[tokio::test]
+async fn reports_a_rejected_stream() {
+    let (mut session, rex) = open(3).await;
+    let mut corrupt = upload();
+    corrupt.crc32 ^= 1;
+    assert!(matches!(session.upload(&corrupt).await, Err(Error::Rejected(Handle::EndStream))));
+    assert_eq!(rex.state().streams_started, 1);
+}
+
+#[test]
+fn garbled_notifications_are_errors() {
+    let reply = Packet::new(None, Handle::Brightness, None, None, vec![0x01]).to_bytes();
+    let notification = Notification::from_vec_u8(reply.clone()).unwrap();
+    assert!(matches!(notification.data(), NotificationType::Brightness(_)));
+
+    let mut bad_marker = reply.clone();
+    bad_marker[0] = 0x55;
+    let mut bad_checksum = reply.clone();
+    *bad_checksum.last_mut().unwrap() ^= 1;
+    let no_data = Packet::new(None, Handle::Continue, None, None, vec![]).to_bytes();
+    for garbled in [vec![], reply[..5].to_vec(), reply[..reply.len() - 1].to_vec(), bad_marker, bad_checksum, no_data] {
+        assert!(matches!(Notification::from_vec_u8(garbled.clone()), Err(Error::BadNotification(..))), "{:02x?}", garbled);
+    }
+
+    let unknown = Packet::new(None, Handle::Unknown, None, None, vec![0x07]).to_bytes();
+    assert!(matches!(Notification::from_vec_u8(unknown).unwrap().data(), NotificationType::Unknown(_)));
+}
@@ -0,0 +1,119 @@
+// Golden images for text rendering. The fonts in fixtures/ are subsets of DejaVu Sans and DejaVu Sans Mono, see
+// fixtures/LICENSE-DejaVu.txt. After an intended change in rendering, regenerate the images with
+// UPDATE_GOLDEN=1 cargo test --test text and look them over before committing.
+use image::{AnimationDecoder, RgbImage, codecs::gif::GifDecoder};
+use iledcolor_rs::{
+    color::Color,
+    config::Panel,
+    fit::FitOptions,
+    font::{OutlineFont, Smoothing},
+    image::ILedImage,
+    text::{self, Align, Direction, MarqueeOptions, TextStyle, Typeface},
+};
+use std::{io::Cursor, path::PathBuf};
+
+const PANEL: Panel = Panel { width: 48, height: 12 };
+// Pixels follow the 22 byte image header.
+const PIXELS: usize = 22;
+const LATIN: &str = "tests/fixtures/DejaVuSans-ascii.ttf";
+const SYMBOLS: &str = "tests/fixtures/DejaVuSansMono-symbols.ttf";
+
+fn pixels(image: &ILedImage) -> RgbImage {
+    let bytes = image.to_bytes();
+    RgbImage::from_raw(PANEL.width as u32, PANEL.height as u32, bytes[PIXELS..].to_vec()).unwrap()
+}
+
+fn outline(fonts: &[&str], smoothing: Smoothing) -> Typeface {
+    Typeface::Outline(OutlineFont::load(fonts, 12.0, smoothing).unwrap())
+}
+
+// Lit pixels as '#', for failure messages that show what was drawn.
+fn sketch(image: &RgbImage) -> String {
+    image
+        .rows()
+        .map(|row| row.map(|p| if p.0 == [0, 0, 0] { '.' } else { '#' }).collect::<String>() + "\n")
+        .collect()
+}
+
+fn assert_golden(name: &str, image: &RgbImage) {
+    let path = PathBuf::from("tests/golden").join(format!("{}.png", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        image.save(&path).unwrap();
+        return;
+    }
+    let golden = image::open(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e)).to_rgb8();
+    assert!(golden == *image, "{} differs from the golden image, drawn:\n{}expected:\n{}", name, sketch(image), sketch(&golden));
+}
+
+#[test]
+fn pixel_font() {
+    let image = text::render("Rex", &Typeface::Pixel, PANEL, &TextStyle::default(), &FitOptions::default());
+    assert_golden("pixel-rex", &pixels(&image));
+}
+
+#[test]
+fn pixel_font_alignment_and_colours() {
+    let style = TextStyle { foreground: Color::new(255, 136, 0), background: Color::new(0, 0, 64), align: Align::Right };
+    let image = pixels(&text::render("555-01", &Typeface::Pixel, PANEL, &style, &FitOptions::default()));
+    assert_eq!(image.get_pixel(0, 0).0, [0, 0, 64]);
+    assert!(image.pixels().any(|p| p.0 == [255, 136, 0]));
+    assert_golden("pixel-right", &image);
+}
+
+#[test]
+fn outline_threshold() {
+    let image = text::render("Rex 555", &outline(&[LATIN], Smoothing::Threshold), PANEL, &TextStyle::default(), &FitOptions::default());
+    let image = pixels(&image);
+    assert!(image.pixels().all(|p| p.0 == [0, 0, 0] || p.0 == [255, 255, 255]));
+    assert_golden("outline-threshold", &image);
+}
+
+#[test]
+fn outline_dither() {
+    let image = text::render("Rex 555", &outline(&[LATIN], Smoothing::Dither), PANEL, &TextStyle::default(), &FitOptions::default());
+    assert_golden("outline-dither", &pixels(&image));
+}
+
+#[test]
+fn outline_antialias() {
+    let image = text::render("Rex 555", &outline(&[LATIN], Smoothing::Antialias), PANEL, &TextStyle::default(), &FitOptions::default());
+    let image = pixels(&image);
+    assert!(image.pixels().any(|p| p.0[0] > 0 && p.0[0] < 255));
+    assert_golden("outline-antialias", &image);
+}
+
+#[test]
+fn fallback_font() {
+    let style = TextStyle { align: Align::Left, ..TextStyle::default() };
+    let with = pixels(&text::render("Rex ♥", &outline(&[LATIN, SYMBOLS], Smoothing::Threshold), PANEL, &style, &FitOptions::default()));
+    let without = pixels(&text::render("Rex ♥", &outline(&[LATIN], Smoothing::Threshold), PANEL, &style, &FitOptions::default()));
+    // Without a fallback the heart is the main font's placeholder box.
+    assert_ne!(with, without);
+    assert_golden("outline-fallback", &with);
+    assert_golden("outline-missing", &without);
+}
+
+#[test]
+fn marquee_frames() {
+    let options = MarqueeOptions { speed: 25, direction: Direction::Left, gap: Some(10) };
+    let animation = text::marquee("Rex", &Typeface::Pixel, PANEL, &TextStyle::default(), &options, &FitOptions::default()).unwrap();
+    let gif = animation.to_bytes();
+    let frames = GifDecoder::new(Cursor::new(&gif[PIXELS..])).unwrap().into_frames().collect_frames().unwrap();
+    // "Rex" is 17 pixels wide in the pixel font, one frame per pixel of text and gap.
+    assert_eq!(frames.len(), 27);
+    assert_eq!(frames[0].delay().numer_denom_ms(), (40, 1));
+    // Every frame is the one before moved a pixel to the left.
+    for pair in frames.windows(2) {
+        let (before, after) = (pair[0].buffer(), pair[1].buffer());
+        for (x, y, pixel) in after.enumerate_pixels().filter(|(x, _, _)| *x < 47) {
+            assert_eq!(pixel, before.get_pixel(x + 1, y));
+        }
+    }
+    assert!(frames[12].buffer().pixels().any(|p| p.0 == [255, 255, 255, 255]));
+}
+
+#[test]
+fn rejects_files_that_are_not_fonts() {
+    assert!(OutlineFont::load(&["tests/fixtures/LICENSE-DejaVu.txt"], 12.0, Smoothing::Threshold).is_err());
+    assert!(OutlineFont::load(&["tests/fixtures/missing.ttf"], 12.0, Smoothing::Threshold).is_err());
+}